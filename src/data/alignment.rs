@@ -0,0 +1,219 @@
+use rustfft::{num_complex::Complex, FftPlanner};
+use serde::Deserialize;
+use std::{cell::RefCell, f32::consts::PI};
+
+/// The smallest drop between two samples that `Alignment::Edge` treats as an edge
+const EDGE_THRESHOLD: f32 = 0.0625;
+
+thread_local! {
+	/// Plans are cached by size, and every frame asks for the same few sizes
+	static PLANNER: RefCell<FftPlanner<f32>> = RefCell::new(FftPlanner::new());
+}
+
+/// How the start of each frame's waveform is chosen, so repeating waves appear to stand still
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Alignment {
+	/// Always start at the beginning of the frame
+	None,
+
+	/// Start at the first sharp drop near the beginning of the frame
+	Edge,
+
+	/// Start at the first point the waveform rises through zero
+	ZeroCrossing,
+
+	/// Estimate the period of the waveform, and start at the same phase of it every frame
+	Autocorrelation,
+}
+
+impl Alignment {
+	/// Chooses the first sample to draw, so that `visible` samples are left after it.
+	/// `raw_samples` holds the `window_len` samples of the visible window, followed by look-ahead
+	/// samples used by the search.
+	pub fn start_sample(&self, raw_samples: &[f32], window_len: usize, visible: usize) -> usize {
+		let max_start = raw_samples.len().saturating_sub(visible + 1);
+
+		match self {
+			Alignment::None => 0,
+			// Only the first 1/15th of the window is searched, which is the spare part of the
+			// default window
+			Alignment::Edge => edge(raw_samples, (window_len / 15).min(max_start)),
+			Alignment::ZeroCrossing => zero_crossing(raw_samples, max_start).unwrap_or(0),
+			Alignment::Autocorrelation => autocorrelation(raw_samples, visible, max_start)
+				.or_else(|| zero_crossing(raw_samples, max_start))
+				.unwrap_or(0),
+		}
+	}
+}
+
+/// Finds the start sample where the waveform best matches the one drawn last frame, using
+/// normalised cross-correlation. Returns `None` when there's nothing to match against.
+pub fn stabilise(raw_samples: &[f32], previous: &[f32], max_start: usize) -> Option<usize> {
+	let length = previous.len().min(raw_samples.len() - max_start);
+	let previous = &previous[..length];
+
+	if length == 0 || previous.iter().all(|&sample| sample == 0.0) {
+		return None;
+	}
+
+	// Running energy of the candidate windows, so louder parts of the frame aren't favoured
+	let mut energy = vec![0.0f32; raw_samples.len() + 1];
+	for (index, sample) in raw_samples.iter().enumerate() {
+		energy[index + 1] = energy[index] + sample * sample;
+	}
+
+	cross_correlation(previous, raw_samples, max_start)
+		.iter()
+		.enumerate()
+		.map(|(start, correlation)| {
+			let window_energy = (energy[start + length] - energy[start]).max(f32::EPSILON);
+
+			(start, correlation / window_energy.sqrt())
+		})
+		.reduce(|a, b| if b.1 > a.1 { b } else { a })
+		.map(|(start, _)| start)
+}
+
+/// Correlates `template` with `signal` at every offset up to `max_offset`, where each value is the
+/// sum of `template[i] * signal[offset + i]`. This is done with an FFT, as checking each offset
+/// in turn gets slow for long windows.
+fn cross_correlation(template: &[f32], signal: &[f32], max_offset: usize) -> Vec<f32> {
+	let size = (template.len() + signal.len()).next_power_of_two();
+	let (forward, inverse) = PLANNER.with(|planner| {
+		let mut planner = planner.borrow_mut();
+		(
+			planner.plan_fft_forward(size),
+			planner.plan_fft_inverse(size),
+		)
+	});
+
+	let transform = |samples: &[f32]| {
+		let mut buffer: Vec<Complex<f32>> = samples
+			.iter()
+			.map(|&sample| Complex::new(sample, 0.0))
+			.collect();
+		buffer.resize(size, Complex::default());
+		forward.process(&mut buffer);
+		buffer
+	};
+
+	let mut product: Vec<Complex<f32>> = transform(template)
+		.iter()
+		.zip(transform(signal))
+		.map(|(template, signal)| template.conj() * signal)
+		.collect();
+	inverse.process(&mut product);
+
+	// rustfft doesn't normalise, so the round trip scales everything by `size`
+	product[..=max_offset]
+		.iter()
+		.map(|value| value.re / size as f32)
+		.collect()
+}
+
+/// Loops through the part of the window that isn't visible, and finds a significant drop in the
+/// signal
+fn edge(raw_samples: &[f32], max_start: usize) -> usize {
+	(0..max_start)
+		.find(|&x| raw_samples[x] - raw_samples[x + 1] >= EDGE_THRESHOLD)
+		.unwrap_or(0)
+}
+
+fn zero_crossing(raw_samples: &[f32], max_start: usize) -> Option<usize> {
+	(0..max_start).find(|&x| raw_samples[x] < 0.0 && raw_samples[x + 1] >= 0.0)
+}
+
+/// Finds the period of the waveform from its autocorrelation, then measures the phase of the
+/// fundamental frequency, and returns the sample where it next rises through zero
+fn autocorrelation(raw_samples: &[f32], visible: usize, max_start: usize) -> Option<usize> {
+	let window = visible.min(raw_samples.len() - visible);
+	let max_lag = (raw_samples.len() - window).min(max_start + 1);
+
+	let correlation = cross_correlation(&raw_samples[..window], raw_samples, max_lag);
+
+	// Silence has no period
+	if correlation[0] <= 0.0 {
+		return None;
+	}
+
+	// Skip the peak around a lag of zero, then look for the strongest repeat. The shortest lag
+	// that's nearly as strong is preferred, so multiples of the period aren't chosen instead.
+	let first_negative = correlation.iter().position(|&value| value < 0.0)?;
+	let strongest = correlation[first_negative..]
+		.iter()
+		.fold(0.0f32, |max, &value| max.max(value));
+
+	if strongest <= 0.0 {
+		return None;
+	}
+
+	let period = (first_negative..correlation.len())
+		.filter(|&lag| lag + 1 >= correlation.len() || correlation[lag] >= correlation[lag + 1])
+		.find(|&lag| correlation[lag] >= strongest * 0.9)?;
+
+	// Phase of the fundamental, measured over as many whole periods as are available
+	let length = (raw_samples.len() / period) * period;
+	let (re, im) =
+		raw_samples[..length]
+			.iter()
+			.enumerate()
+			.fold((0.0, 0.0), |(re, im), (index, sample)| {
+				let angle = 2.0 * PI * (index % period) as f32 / period as f32;
+				(re + sample * angle.cos(), im - sample * angle.sin())
+			});
+	let phase = im.atan2(re);
+
+	// The fundamental rises through zero at a phase of -PI/2
+	let cycles = (-PI / 2.0 - phase) / (2.0 * PI);
+	let start = ((cycles - cycles.floor()) * period as f32).round() as usize % period;
+
+	Some(start.min(max_start))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Alignment;
+
+	#[test]
+	fn autocorrelation_locks_pulse_with_changing_duty_cycle() {
+		let period = 100;
+		let frame_step = 735;
+
+		let phases: Vec<f32> = (0..40)
+			.map(|frame| {
+				// The pulse widens from a quarter to half of the period over the frames
+				let duty = 0.25 + 0.25 * frame as f32 / 40.0;
+				let offset = frame * frame_step;
+				let raw_samples: Vec<f32> = (offset..offset + 1600)
+					.map(|index| {
+						let phase = (index % period) as f32 / period as f32;
+						if phase < duty {
+							0.5
+						} else {
+							-0.5
+						}
+					})
+					.collect();
+
+				let window_len = raw_samples.len() / 2;
+				let visible = window_len - window_len / 15;
+				let start =
+					Alignment::Autocorrelation.start_sample(&raw_samples, window_len, visible);
+
+				// Widening the pulse moves its centre, which the fundamental follows, so the
+				// phase is measured from there
+				let centre = duty * period as f32 / 2.0;
+				((offset + start) as f32 - centre).rem_euclid(period as f32)
+			})
+			.collect();
+
+		for (frame, phase) in phases.iter().enumerate() {
+			assert!(
+				(phase - phases[0]).abs() <= 1.0,
+				"frame {frame} started at phase {phase}, expected {}",
+				phases[0]
+			);
+		}
+	}
+}
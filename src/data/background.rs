@@ -0,0 +1,74 @@
+use super::error::LoadError;
+use crate::{display::RGB, SCREEN_HEIGHT, SCREEN_WIDTH};
+use image::{imageops, imageops::FilterType, RgbImage};
+use serde::Deserialize;
+
+/// How the background image is scaled to fit the screen
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BackgroundMode {
+	/// Scale the image to fit inside the screen, leaving bars of the theme's background colour
+	Fit,
+
+	/// Scale the image to cover the whole screen, cropping the edges that don't fit
+	#[default]
+	Fill,
+
+	/// Repeat the image at its original size, from the top left corner
+	Tile,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct BackgroundSettings {
+	/// Path to a PNG image
+	pub file: String,
+
+	#[serde(default)]
+	pub mode: BackgroundMode,
+}
+
+impl BackgroundSettings {
+	/// Loads the image and scales it to the base resolution, ready to be copied into every frame.
+	/// Anything the image doesn't cover is filled with `colour`.
+	pub fn load(&self, colour: RGB) -> Result<RgbImage, LoadError> {
+		let image = image::open(&self.file)
+			.map_err(|err| LoadError::image(&self.file, err))?
+			.to_rgb8();
+
+		let (width, height) = (*SCREEN_WIDTH, *SCREEN_HEIGHT);
+		let mut background = RgbImage::from_pixel(width, height, image::Rgb(colour));
+
+		match self.mode {
+			BackgroundMode::Fit | BackgroundMode::Fill => {
+				let scale_x = width as f32 / image.width() as f32;
+				let scale_y = height as f32 / image.height() as f32;
+				let scale = if self.mode == BackgroundMode::Fit {
+					scale_x.min(scale_y)
+				} else {
+					scale_x.max(scale_y)
+				};
+
+				let scaled = imageops::resize(
+					&image,
+					((image.width() as f32 * scale).round() as u32).max(1),
+					((image.height() as f32 * scale).round() as u32).max(1),
+					FilterType::Triangle,
+				);
+
+				// Centre the image, so `Fill` crops the same amount from each side
+				let x = (width as i64 - scaled.width() as i64) / 2;
+				let y = (height as i64 - scaled.height() as i64) / 2;
+				imageops::replace(&mut background, &scaled, x, y);
+			}
+			BackgroundMode::Tile => {
+				for y in (0..height).step_by(image.height() as usize) {
+					for x in (0..width).step_by(image.width() as usize) {
+						imageops::replace(&mut background, &image, x as i64, y as i64);
+					}
+				}
+			}
+		}
+
+		Ok(background)
+	}
+}
@@ -1,30 +1,185 @@
-use super::defaults::default_true;
+use super::{
+	alignment::{self, Alignment},
+	defaults::{default_one, default_true},
+	error::LoadError,
+	lane::{Lane, LaneSettings},
+	layout::{RectConfig, Span},
+	scope::{Phosphor, XySettings},
+	silence::{SilenceDetector, SilenceSettings},
+	spectrum::{Spectrogram, SpectrogramSettings, Spectrum, SpectrumSettings, FFT_SIZE},
+	stream::{DecodeJob, DecodeStream},
+	style::{DisplayMode, WaveformStyle},
+	theme::Theme,
+	window::FrameRate,
+};
 use crate::{display::RGB, SCREEN_FRAME_RATE};
 use rayon::prelude::*;
 use serde::Deserialize;
 use std::collections::VecDeque;
 use symphonia::core::{
-	audio::{AudioBufferRef, Signal},
+	audio::{AudioBuffer, AudioBufferRef, Signal},
 	codecs::{Decoder, CODEC_TYPE_NULL},
-	errors::Error,
-	formats::{FormatOptions, FormatReader, Track},
+	conv::IntoSample,
+	errors::{Error as SymphoniaError, SeekErrorKind},
+	formats::{FormatOptions, FormatReader, SeekMode, SeekTo, Track},
 	io::MediaSourceStream,
 	meta::MetadataOptions,
 	probe::Hint,
+	sample::Sample,
 };
 
+/// Which part of a stereo stem is displayed. Mono stems display the same for every mode.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum StereoMode {
+	/// Only the left channel
+	#[default]
+	Left,
+
+	/// Only the right channel
+	Right,
+
+	/// The average of the left and right channels
+	Downmix,
+
+	/// The mid (L+R) and side (L-R) signals, as two stacked traces
+	MidSide,
+
+	/// The left and right channels, as two stacked traces
+	Split,
+}
+
+impl StereoMode {
+	/// How many waveforms are drawn in the channel's cell
+	pub fn trace_count(&self) -> usize {
+		match self {
+			StereoMode::MidSide | StereoMode::Split => 2,
+			_ => 1,
+		}
+	}
+
+	/// Combines the left and right channels into the traces displayed for this mode
+	pub fn mix(&self, left: Vec<f32>, right: Vec<f32>) -> Vec<Vec<f32>> {
+		let average = |left: &[f32], right: &[f32]| {
+			left.par_iter()
+				.zip(right.par_iter())
+				.map(|(l, r)| (l + r) / 2.0)
+				.collect()
+		};
+
+		match self {
+			StereoMode::Left => vec![left],
+			StereoMode::Right => vec![right],
+			StereoMode::Downmix => vec![average(&left, &right)],
+			StereoMode::MidSide => {
+				let side = left
+					.par_iter()
+					.zip(right.par_iter())
+					.map(|(l, r)| (l - r) / 2.0)
+					.collect();
+
+				vec![average(&left, &right), side]
+			}
+			StereoMode::Split => vec![left, right],
+		}
+	}
+}
+
+/// How the waveform is scaled to fit the channel's cell, based on the level of the whole file
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalize {
+	/// Display the waveform at its original level
+	#[default]
+	None,
+
+	/// Scale the loudest sample to the edge of the cell
+	Peak,
+
+	/// Scale the average level to `RMS_TARGET`, without letting the peak clip
+	Rms,
+}
+
+/// The RMS level that `Normalize::Rms` scales each channel to
+const RMS_TARGET: f32 = 0.5;
+
 #[derive(Deserialize)]
 pub struct Channel {
 	pub name: String,
 	pub file: String,
 
-	/// Colour is optional, and will default to black, ie. [0,0,0]
-	#[serde(default)]
-	pub colour: RGB,
+	/// Colour is optional, and will default to the theme's channel colour
+	pub colour: Option<RGB>,
 
+	/// How see-through the channel's background is, from 0.0 (invisible) to 1.0 (solid)
+	#[serde(default = "default_one")]
+	pub background_opacity: f32,
+
+	/// Superseded by `alignment`, but still used when it isn't set
 	#[serde(default = "default_true")]
 	pub use_alignment: bool,
 
+	pub alignment: Option<Alignment>,
+
+	/// Shift the waveform to best match the previous frame, so slowly changing tones stay still
+	#[serde(default)]
+	pub stabilize: bool,
+
+	#[serde(default)]
+	pub stereo_mode: StereoMode,
+
+	/// Multiplier applied to every sample, after normalisation
+	#[serde(default = "default_one")]
+	pub gain: f32,
+
+	#[serde(default)]
+	pub normalize: Normalize,
+
+	/// Shifts this stem in time. Positive values skip audio at the start of the file (eg. extra
+	/// leading silence), negative values delay it.
+	#[serde(default)]
+	pub offset_secs: f64,
+
+	/// How much audio is stretched across the width of the cell, in milliseconds. Defaults to the
+	/// length of one video frame.
+	pub window_ms: Option<f64>,
+
+	#[serde(default)]
+	pub waveform_style: WaveformStyle,
+
+	#[serde(default)]
+	pub display: DisplayMode,
+
+	/// Only used when `display` is `Spectrum`
+	#[serde(default)]
+	pub spectrum: SpectrumSettings,
+
+	/// Only used when `display` is `Spectrogram`
+	#[serde(default)]
+	pub spectrogram: SpectrogramSettings,
+
+	/// Only used when `display` is `Xy`
+	#[serde(default)]
+	pub xy: XySettings,
+
+	/// Only used when `display` is `Lane`
+	#[serde(default)]
+	pub lane: LaneSettings,
+
+	/// Draw the channel here, instead of in the layout grid
+	pub rect: Option<RectConfig>,
+
+	/// How many columns and rows of the layout grid the channel covers
+	#[serde(default)]
+	pub span: Span,
+
+	/// How big the channel's columns and rows are, compared to the others
+	#[serde(default = "default_one")]
+	pub weight: f32,
+
+	/// Dim or collapse the channel while it's silent
+	pub silence: Option<SilenceSettings>,
+
 	#[serde(skip)]
 	pub track: Option<Track>,
 
@@ -34,16 +189,65 @@ pub struct Channel {
 	#[serde(skip)]
 	pub decoder: Option<Box<dyn Decoder>>,
 
+	/// One buffer per trace, see `StereoMode::trace_count`
 	#[serde(skip)]
-	pub buffer: VecDeque<u8>,
+	pub buffer: Vec<VecDeque<f32>>,
 
 	#[serde(skip)]
 	pub play_time_samples: u64,
 
 	#[serde(skip)]
 	pub play_time_samples_total: u64,
+
+	/// Combined gain and normalisation, applied to every decoded sample
+	#[serde(skip)]
+	pub level_scale: f32,
+
+	/// The whole file has been decoded, any further samples are silence
+	#[serde(skip)]
+	pub finished: bool,
+
+	/// Decodes ahead of the playhead on a worker thread, once the channel has started playing.
+	/// Takes ownership of `format` and `decoder`.
+	#[serde(skip)]
+	pub stream: Option<DecodeStream>,
+
+	/// Decoded samples to throw away, when a seek lands before the requested time
+	#[serde(skip)]
+	pub skip_samples: usize,
+
+	/// Frequency analysis for each trace, when `display` is `Spectrum`
+	#[serde(skip)]
+	pub spectra: Vec<Spectrum>,
+
+	/// Scrolling frequency history for each trace, when `display` is `Spectrogram`
+	#[serde(skip)]
+	pub spectrograms: Vec<Spectrogram>,
+
+	/// Level history for each trace, when `display` is `Lane`
+	#[serde(skip)]
+	pub lanes: Vec<Lane>,
+
+	/// The glowing trace left by previous frames, when `display` is `Xy`
+	#[serde(skip)]
+	pub phosphor: Option<Phosphor>,
+
+	/// How long the channel has been silent, when `silence` is set
+	#[serde(skip)]
+	pub silence_detector: Option<SilenceDetector>,
+
+	/// The samples drawn for each trace last frame, used by `stabilize`
+	#[serde(skip)]
+	pub previous_traces: Vec<Vec<f32>>,
+
+	/// Leftover fraction of a sample from previous frames, in units of 1/numerator of the frame rate
+	#[serde(skip)]
+	pub frame_sample_remainder: usize,
 }
 
+/// A newly opened audio file, ready to decode
+type OpenTrack = (Box<dyn FormatReader>, Track, Box<dyn Decoder>);
+
 #[derive(Debug)]
 pub enum SongError {
 	Error(symphonia::core::errors::Error),
@@ -51,19 +255,52 @@ pub enum SongError {
 }
 
 impl Channel {
-	pub fn load_track_into_memory(&mut self) {
-		// Open the media source.
-		let src = std::fs::File::open(&self.file);
+	pub fn load_track_into_memory(&mut self) -> Result<(), LoadError> {
+		let (format, track, decoder) = self.open_track()?;
+
+		// Some formats (eg. OGG, VBR MP3) don't declare their length up front
+		self.play_time_samples_total = match track.codec_params.n_frames {
+			Some(n_frames) => n_frames,
+			None => self.count_frames()?,
+		};
+		self.buffer = vec![VecDeque::new(); self.mix_mode().trace_count()];
+		self.previous_traces = vec![Vec::new(); self.mix_mode().trace_count()];
+		let sample_rate = track.codec_params.sample_rate.unwrap();
+		let traces = 0..self.mix_mode().trace_count();
+		match self.display {
+			DisplayMode::Spectrum => {
+				self.spectra = traces
+					.map(|_| Spectrum::new(self.spectrum, sample_rate))
+					.collect();
+			}
+			DisplayMode::Spectrogram => {
+				self.spectrograms = traces
+					.map(|_| Spectrogram::new(self.spectrogram, sample_rate))
+					.collect();
+			}
+			DisplayMode::Lane => {
+				self.lanes = traces.map(|_| Lane::new(self.lane, sample_rate)).collect()
+			}
+			DisplayMode::Xy => self.phosphor = Some(Phosphor::new(self.xy)),
+			DisplayMode::Waveform => {}
+		}
+		self.silence_detector = self.silence.map(SilenceDetector::new);
 
-		let print_error =
-			|error: &str| format!("Could not load track \"{}\" - Error: {}", &self.file, error);
+		self.format = Some(format);
+		self.track = Some(track);
+		self.decoder = Some(decoder);
 
-		if let Err(err) = src {
-			println!("\n{}\n", print_error(&err.to_string()));
-			std::process::exit(1);
+		self.level_scale = self.gain;
+		if self.normalize != Normalize::None {
+			self.level_scale *= self.normalize_scale()?;
 		}
 
-		let src = src.unwrap();
+		Ok(())
+	}
+
+	fn open_track(&self) -> Result<OpenTrack, LoadError> {
+		// Open the media source.
+		let src = std::fs::File::open(&self.file).map_err(|err| LoadError::io(&self.file, err))?;
 
 		// Create the media source stream.
 		let mss = MediaSourceStream::new(Box::new(src), Default::default());
@@ -80,7 +317,7 @@ impl Channel {
 		// Probe the media source.
 		let probed = symphonia::default::get_probe()
 			.format(&hint, mss, &fmt_opts, &meta_opts)
-			.expect(&print_error("Unsupported format"));
+			.map_err(|err| LoadError::audio(&self.file, format!("Unsupported format ({err})")))?;
 
 		// Get the instantiated format reader.
 		let format = probed.format;
@@ -90,141 +327,459 @@ impl Channel {
 			.tracks()
 			.iter()
 			.find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-			.expect(&print_error("No supported audio tracks"))
+			.ok_or_else(|| LoadError::audio(&self.file, "No supported audio tracks"))?
 			.clone();
 
+		if track.codec_params.sample_rate.is_none() {
+			return Err(LoadError::audio(&self.file, "Unknown sample rate"));
+		}
+
 		let codec = track.codec_params.codec.to_string();
 
 		let decoder = symphonia::default::get_codecs()
 			.make(&track.codec_params, &Default::default())
-			.expect(&print_error(&format!("{codec} is an unsupported codec")));
+			.map_err(|_| {
+				LoadError::audio(&self.file, format!("{codec} is an unsupported codec"))
+			})?;
 
-		self.play_time_samples_total = track.codec_params.n_frames.unwrap();
-		self.format = Some(format);
-		self.track = Some(track);
-		self.decoder = Some(decoder);
+		Ok((format, track, decoder))
 	}
 
-	pub fn get_frame_samples(&mut self) -> Result<Vec<u8>, SongError> {
-		let format = self.format.as_mut().unwrap();
-		let track = self.track.as_mut().unwrap();
-		let decoder = self.decoder.as_mut().unwrap();
-		let min_samples_required =
-			track.codec_params.sample_rate.unwrap() as usize / *SCREEN_FRAME_RATE;
+	/// Works out the length of the file, in samples, by reading every packet. Packets are only
+	/// decoded when they don't state their own duration.
+	fn count_frames(&self) -> Result<u64, LoadError> {
+		let (mut format, track, mut decoder) = self.open_track()?;
+		let sample_rate = track.codec_params.sample_rate.unwrap() as f64;
 
-		let mut retries = 100;
+		let mut end_ts = 0;
+		let mut decoded_frames = 0;
 
-		if self.buffer.capacity() < min_samples_required {
-			self.buffer
-				.reserve(min_samples_required - self.buffer.capacity())
-		}
+		// Any read error at this point is treated as the end of the file
+		while let Ok(packet) = format.next_packet() {
+			if packet.track_id() != track.id {
+				continue;
+			}
 
-		let print_error = |err: &str| println!("Error rendering \"{}\": {}", &self.file, err);
+			if packet.dur > 0 {
+				end_ts = end_ts.max(packet.ts + packet.dur);
+			} else if let Ok(decoded) = decoder.decode(&packet) {
+				decoded_frames += decoded.frames() as u64;
+			}
+		}
 
-		while self.buffer.len() < min_samples_required || retries > 0 {
-			// loop of death prevention measure
-			retries -= 1;
+		// Timestamps are in the track's time base, which isn't always one tick per sample
+		let timestamp_frames = match track.codec_params.time_base {
+			Some(time_base) => {
+				let time = time_base.calc_time(end_ts);
+				((time.seconds as f64 + time.frac) * sample_rate).round() as u64
+			}
+			None => end_ts,
+		};
+
+		match timestamp_frames.max(decoded_frames) {
+			0 => Err(LoadError::audio(
+				&self.file,
+				"Could not work out the length",
+			)),
+			frames => Ok(frames),
+		}
+	}
 
-			// Get the next packet from the media format.
-			let packet = match format.next_packet() {
-				Ok(packet) => packet,
-				Err(Error::IoError(_err)) => {
-					if self.play_time_samples
-						>= self.play_time_samples_total - min_samples_required as u64
-					{
-						return Err(SongError::End);
-					}
-					continue;
-				}
-				Err(err) => {
-					// A unrecoverable error occured, halt decoding.
-					return Err(SongError::Error(err));
-				}
-			};
+	/// Decodes the whole file once, and returns the multiplier needed to reach the level chosen
+	/// by `normalize`
+	fn normalize_scale(&self) -> Result<f32, LoadError> {
+		let (mut format, track, mut decoder) = self.open_track()?;
 
-			// Consume any new metadata that has been read since the last packet.
-			while !format.metadata().is_latest() {
-				// Pop the old head of the metadata queue.
-				format.metadata().pop();
+		let mut peak = 0.0f32;
+		let mut sum_squares = 0.0f64;
+		let mut sample_count = 0usize;
 
-				// Consume the new metadata at the head of the metadata queue.
+		// Any read error at this point is treated as the end of the file
+		while let Ok(packet) = format.next_packet() {
+			if packet.track_id() != track.id {
+				continue;
 			}
 
-			if packet.track_id() != track.id {
-				print_error("Track doesn't match, skipping...");
+			let Ok(decoded) = decoder.decode(&packet) else {
 				continue;
+			};
+
+			let (left, right) = stereo_pair_ref(decoded);
+			for trace in self.mix_mode().mix(left, right) {
+				peak = trace
+					.iter()
+					.fold(peak, |peak, sample| peak.max(sample.abs()));
+				sum_squares += trace
+					.iter()
+					.map(|sample| (sample * sample) as f64)
+					.sum::<f64>();
+				sample_count += trace.len();
 			}
+		}
 
-			// Decode the packet into audio samples.
-			match decoder.decode(&packet) {
-				Ok(decoded) => match decoded {
-					AudioBufferRef::F32(buf) => {
-						let mut samples = buf
-							.chan(0)
-							.par_iter()
-							.map(|sample| ((sample * 128.0) + 128.0) as u8)
-							.collect::<VecDeque<u8>>();
-						self.buffer.append(&mut samples);
-					}
-					AudioBufferRef::F64(buf) => {
-						let mut samples = buf
-							.chan(0)
-							.par_iter()
-							.map(|sample| ((sample * 128.0) + 128.0) as u8)
-							.collect::<VecDeque<u8>>();
-						self.buffer.append(&mut samples);
-					}
-					AudioBufferRef::S16(buf) => {
-						let mut samples = buf
-							.chan(0)
-							.par_iter()
-							.map(|sample| ((*sample / 2i16.pow(8)) + 128) as u8)
-							.collect::<VecDeque<u8>>();
-						self.buffer.append(&mut samples);
-					}
-					AudioBufferRef::S24(buf) => {
-						let mut samples = buf
-							.chan(0)
-							.par_iter()
-							.map(|sample| ((sample.0 / 2i32.pow(16)) + 128) as u8)
-							.collect::<VecDeque<u8>>();
-						self.buffer.append(&mut samples);
-					}
-					AudioBufferRef::S32(buf) => {
-						let mut samples = buf
-							.chan(0)
-							.par_iter()
-							.map(|sample| ((*sample / 2i32.pow(24)) + 128) as u8)
-							.collect::<VecDeque<u8>>();
-						self.buffer.append(&mut samples);
-					}
-					AudioBufferRef::U8(buf) => {
-						// Format already u8, just copy directly
-						self.buffer.extend(buf.chan(0).iter());
-					}
-					_ => {
-						// Repeat for the different sample formats.
-						print_error("format not supported");
-						unimplemented!()
-					}
+		// Silent files are left alone
+		if peak == 0.0 {
+			return Ok(1.0);
+		}
+
+		let rms = (sum_squares / sample_count as f64).sqrt() as f32;
+
+		Ok(match self.normalize {
+			Normalize::None => 1.0,
+			Normalize::Peak => 1.0 / peak,
+			Normalize::Rms => (RMS_TARGET / rms).min(1.0 / peak),
+		})
+	}
+
+	/// Moves playback to `song_secs` into the song, taking `offset_secs` into account
+	pub fn seek(&mut self, song_secs: f64) -> Result<(), LoadError> {
+		let format = self.format.as_mut().unwrap();
+		let track = self.track.as_ref().unwrap();
+		let decoder = self.decoder.as_mut().unwrap();
+		let sample_rate = track.codec_params.sample_rate.unwrap() as f64;
+		let file_secs = song_secs + self.offset_secs;
+		let duration_secs = self.play_time_samples_total as f64 / sample_rate;
+
+		if file_secs >= duration_secs {
+			// The stem has already ended, so it's padded with silence for the rest of the song
+			self.finished = true;
+		} else if file_secs > 0.0 {
+			let seeked = format.seek(
+				SeekMode::Accurate,
+				SeekTo::Time {
+					time: file_secs.into(),
+					track_id: Some(track.id),
 				},
-				Err(Error::IoError(_)) => {
-					// The packet failed to decode due to an IO error, skip the packet.
-					continue;
-				}
-				Err(Error::DecodeError(_)) => {
-					// The packet failed to decode due to invalid data, skip the packet.
-					continue;
+			);
+
+			match seeked {
+				Ok(seeked_to) => {
+					decoder.reset();
+
+					// Accurate seeking can still land on the packet before the requested time
+					let early = seeked_to.required_ts.saturating_sub(seeked_to.actual_ts);
+					self.skip_samples = match track.codec_params.time_base {
+						Some(time_base) => {
+							let time = time_base.calc_time(early);
+							((time.seconds as f64 + time.frac) * sample_rate) as usize
+						}
+						None => early as usize,
+					};
 				}
+				// Declared lengths aren't always exact, so the stem may end a little sooner
+				Err(SymphoniaError::SeekError(SeekErrorKind::OutOfRange)) => self.finished = true,
 				Err(err) => {
-					// An unrecoverable error occured, halt decoding.
-					return Err(SongError::Error(err));
+					return Err(LoadError::audio(
+						&self.file,
+						format!("Could not seek ({err})"),
+					))
+				}
+			}
+		} else if file_secs < 0.0 {
+			// Pad with silence until the stem starts
+			let silence = (-file_secs * sample_rate) as usize;
+			for buffer in &mut self.buffer {
+				buffer.extend(std::iter::repeat_n(0.0, silence));
+			}
+		}
+
+		self.play_time_samples = (song_secs * sample_rate) as u64;
+
+		Ok(())
+	}
+
+	/// Fills in the colours the config leaves out
+	pub fn apply_theme(&mut self, theme: &Theme) {
+		self.colour.get_or_insert(theme.channel);
+		self.waveform_style.colour.get_or_insert(theme.foreground);
+	}
+
+	/// How the decoded audio is split into traces. XY plots always need both sides.
+	pub fn mix_mode(&self) -> StereoMode {
+		match self.display {
+			DisplayMode::Xy => StereoMode::Split,
+			_ => self.stereo_mode,
+		}
+	}
+
+	/// Whether the channel has been silent long enough to give up its cell
+	pub fn is_collapsed(&self) -> bool {
+		self.silence_detector
+			.as_ref()
+			.is_some_and(|detector| detector.is_collapsed())
+	}
+
+	/// How the waveform is aligned each frame. Older configs only choose whether to use `Edge`.
+	pub fn alignment(&self) -> Alignment {
+		match self.alignment {
+			Some(alignment) => alignment,
+			None if self.use_alignment => Alignment::Edge,
+			None => Alignment::None,
+		}
+	}
+
+	/// Chooses the part of a trace from `get_frame_samples` to draw this frame. Channels with a
+	/// `window_ms` draw the whole window, and the look-ahead leaves room to line it up. Otherwise
+	/// ~6% of the window is left spare, as it always has been.
+	pub fn visible_samples<'a>(&mut self, trace: usize, raw_samples: &'a [f32]) -> &'a [f32] {
+		let window_len = raw_samples.len() / 2;
+		let visible = match self.window_ms {
+			Some(_) => window_len,
+			None => window_len - window_len / 15,
+		};
+		let max_start = raw_samples.len() - visible - 1;
+
+		let mode = self.alignment();
+		let previous = &mut self.previous_traces[trace];
+		let stabilised = if self.stabilize {
+			alignment::stabilise(raw_samples, previous, max_start)
+		} else {
+			None
+		};
+		let start_sample =
+			stabilised.unwrap_or_else(|| mode.start_sample(raw_samples, window_len, visible));
+
+		// One extra sample, so the last pixel can be interpolated
+		let samples = &raw_samples[start_sample..=start_sample + visible];
+		if self.stabilize {
+			*previous = samples.to_vec();
+		}
+
+		samples
+	}
+
+	/// The length of this stem in the song, in seconds
+	pub fn get_duration_secs(&self) -> f64 {
+		let sample_rate = self
+			.track
+			.as_ref()
+			.unwrap()
+			.codec_params
+			.sample_rate
+			.unwrap();
+
+		(self.play_time_samples_total as f64 / sample_rate as f64) - self.offset_secs
+	}
+
+	/// How many samples make up the next frame. When the sample rate doesn't divide evenly by the
+	/// frame rate, the remainder is carried over so the video never drifts away from the audio.
+	pub fn next_frame_sample_count(&mut self, sample_rate: usize, frame_rate: FrameRate) -> usize {
+		let samples = sample_rate * frame_rate.denominator + self.frame_sample_remainder;
+		self.frame_sample_remainder = samples % frame_rate.numerator;
+
+		samples / frame_rate.numerator
+	}
+
+	/// Starts decoding on a worker thread, from wherever the channel was last seeked to
+	pub fn start_decoding(&mut self) {
+		// Stems seeked past their end have nothing left to decode
+		if self.finished {
+			return;
+		}
+
+		let track = self.track.as_ref().unwrap();
+
+		self.stream = Some(DecodeStream::spawn(DecodeJob {
+			file: self.file.clone(),
+			format: self.format.take().unwrap(),
+			decoder: self.decoder.take().unwrap(),
+			track_id: track.id,
+			sample_rate: track.codec_params.sample_rate.unwrap(),
+			stereo_mode: self.mix_mode(),
+			level_scale: self.level_scale,
+			skip_samples: self.skip_samples,
+		}));
+	}
+
+	/// Returns the samples in the visible window of every trace, starting at the playhead, followed
+	/// by the same number of look-ahead samples which are used to align the waveform. The playhead
+	/// then moves forward by one frame, and the number of samples it moved by is returned too.
+	pub fn get_frame_samples(&mut self) -> Result<(Vec<Vec<f32>>, usize), SongError> {
+		let sample_rate = self
+			.track
+			.as_ref()
+			.unwrap()
+			.codec_params
+			.sample_rate
+			.unwrap();
+		let min_samples_required =
+			self.next_frame_sample_count(sample_rate as usize, *SCREEN_FRAME_RATE);
+
+		let window_samples = match (self.display, self.window_ms) {
+			// Frequency analysis uses the window and look-ahead together
+			(DisplayMode::Spectrum | DisplayMode::Spectrogram, _) => FFT_SIZE / 2,
+			// Lanes read ahead of the playhead, by half of the lane
+			(DisplayMode::Lane, _) => Lane::samples_ahead(self.lane, sample_rate).div_ceil(2),
+			(_, Some(window_ms)) => ((window_ms / 1000.0 * sample_rate as f64) as usize).max(2),
+			(_, None) => min_samples_required,
+		};
+		let samples_returned = window_samples * 2;
+		let samples_required = samples_returned.max(min_samples_required);
+
+		// Only waits on the decoder thread if it has fallen behind the playhead
+		while !self.finished && self.buffer[0].len() < samples_required {
+			match self.stream.as_ref().unwrap().next_block() {
+				Some(Ok(traces)) => {
+					for (buffer, trace) in self.buffer.iter_mut().zip(traces) {
+						buffer.extend(trace);
+					}
 				}
+				Some(Err(err)) => return Err(SongError::Error(err)),
+				// The end of the file, nothing more to decode
+				None => self.finished = true,
+			}
+		}
+
+		// Pad finished channels with silence, so they can keep playing until the song ends
+		for buffer in &mut self.buffer {
+			if buffer.len() < samples_required {
+				buffer.resize(samples_required, 0.0);
 			}
 		}
 
 		self.play_time_samples += min_samples_required as u64;
 
-		Ok(self.buffer.drain(0..min_samples_required).collect())
+		let traces = self
+			.buffer
+			.iter_mut()
+			.map(|buffer| {
+				let samples = buffer.range(0..samples_returned).copied().collect();
+				buffer.drain(0..min_samples_required);
+				samples
+			})
+			.collect();
+
+		Ok((traces, min_samples_required))
+	}
+}
+
+/// Converts any decoded buffer to a pair of `f32` channels, see `stereo_pair`
+pub fn stereo_pair_ref(decoded: AudioBufferRef) -> (Vec<f32>, Vec<f32>) {
+	match decoded {
+		AudioBufferRef::U8(buf) => stereo_pair(&buf),
+		AudioBufferRef::U16(buf) => stereo_pair(&buf),
+		AudioBufferRef::U24(buf) => stereo_pair(&buf),
+		AudioBufferRef::U32(buf) => stereo_pair(&buf),
+		AudioBufferRef::S8(buf) => stereo_pair(&buf),
+		AudioBufferRef::S16(buf) => stereo_pair(&buf),
+		AudioBufferRef::S24(buf) => stereo_pair(&buf),
+		AudioBufferRef::S32(buf) => stereo_pair(&buf),
+		AudioBufferRef::F32(buf) => stereo_pair(&buf),
+		AudioBufferRef::F64(buf) => stereo_pair(&buf),
+	}
+}
+
+/// Converts the first two channels of a decoded buffer to `f32` samples, in the range -1.0 to
+/// 1.0. Mono buffers return the same samples for both sides.
+fn stereo_pair<S: Sample + IntoSample<f32> + Sync>(buf: &AudioBuffer<S>) -> (Vec<f32>, Vec<f32>) {
+	let left = buf
+		.chan(0)
+		.par_iter()
+		.map(|sample| (*sample).into_sample())
+		.collect::<Vec<f32>>();
+
+	if buf.spec().channels.count() < 2 {
+		return (left.clone(), left);
+	}
+
+	let right = buf
+		.chan(1)
+		.par_iter()
+		.map(|sample| (*sample).into_sample())
+		.collect::<Vec<f32>>();
+
+	(left, right)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Channel, FrameRate};
+
+	fn empty_channel() -> Channel {
+		serde_json::from_str(r#"{ "name": "Test", "file": "test.wav" }"#).unwrap()
+	}
+
+	/// Writes a second of silent 16-bit mono audio, and returns a channel that plays it
+	fn one_second_channel(name: &str, offset_secs: f64) -> Channel {
+		let sample_rate: u32 = 8000;
+		let data_len = sample_rate * 2;
+
+		let mut wav = Vec::new();
+		wav.extend(b"RIFF");
+		wav.extend((36 + data_len).to_le_bytes());
+		wav.extend(b"WAVEfmt ");
+		wav.extend(16u32.to_le_bytes());
+		wav.extend(1u16.to_le_bytes());
+		wav.extend(1u16.to_le_bytes());
+		wav.extend(sample_rate.to_le_bytes());
+		wav.extend((sample_rate * 2).to_le_bytes());
+		wav.extend(2u16.to_le_bytes());
+		wav.extend(16u16.to_le_bytes());
+		wav.extend(b"data");
+		wav.extend(data_len.to_le_bytes());
+		wav.resize(wav.len() + data_len as usize, 0);
+
+		let path = std::env::temp_dir().join(format!("multitrack-visualizer-{name}.wav"));
+		std::fs::write(&path, wav).unwrap();
+
+		let mut channel = empty_channel();
+		channel.file = path.to_string_lossy().into_owned();
+		channel.offset_secs = offset_secs;
+		channel
+	}
+
+	#[test]
+	fn seeking_past_the_end_pads_with_silence() {
+		for (name, song_secs, offset_secs) in [("start", 2.0, 0.0), ("offset", 0.0, 5.0)] {
+			let mut channel = one_second_channel(name, offset_secs);
+			channel.load_track_into_memory().unwrap();
+
+			assert!(channel.seek(song_secs).is_ok(), "seeking with {name}");
+			assert!(channel.finished);
+
+			// Nothing is left to decode, so the frames are filled with silence instead
+			channel.start_decoding();
+			assert!(channel.stream.is_none());
+		}
+
+		let mut channel = one_second_channel("inside", 0.0);
+		channel.load_track_into_memory().unwrap();
+		channel.seek(0.5).unwrap();
+		assert!(!channel.finished);
+	}
+
+	#[test]
+	fn frame_sample_count_has_no_drift() {
+		let rates = [
+			(44100, FrameRate::new(60, 1)),
+			(44100, FrameRate::new(25, 1)),
+			(48000, FrameRate::new(30, 1)),
+			(22050, FrameRate::new(60, 1)),
+			(32000, FrameRate::new(24, 1)),
+			(48000, FrameRate::new(30000, 1001)),
+		];
+
+		for (sample_rate, frame_rate) in rates {
+			let mut channel = empty_channel();
+			let (numerator, denominator) = (frame_rate.numerator, frame_rate.denominator);
+			let frames_per_hour = 60 * 60 * numerator / denominator;
+			let average = sample_rate * denominator / numerator;
+
+			let mut total = 0;
+			for _ in 0..frames_per_hour {
+				let samples = channel.next_frame_sample_count(sample_rate, frame_rate);
+				assert!(samples == average || samples == average + 1);
+				total += samples;
+			}
+
+			// Every sample is accounted for, down to the fraction still carried over
+			let exact = frames_per_hour * sample_rate * denominator;
+			assert_eq!(
+				total,
+				exact / numerator,
+				"{sample_rate} Hz at {numerator}/{denominator} fps"
+			);
+			assert_eq!(channel.frame_sample_remainder, exact % numerator);
+		}
 	}
 }
@@ -9,3 +9,7 @@ pub fn default_output() -> String {
 pub fn default_five() -> f64 {
 	5.0
 }
+
+pub fn default_one() -> f32 {
+	1.0
+}
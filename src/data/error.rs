@@ -0,0 +1,130 @@
+use std::fmt;
+
+/// Anything that can go wrong while loading config, audio, or MIDI files, before rendering starts
+#[derive(Debug)]
+pub enum LoadError {
+	/// A file could not be opened or read
+	Io { path: String, error: std::io::Error },
+
+	/// A JSON config file could not be parsed, or contains invalid values
+	Config { path: String, reason: String },
+
+	/// An audio file could not be probed, decoded, or seeked
+	Audio { path: String, reason: String },
+
+	/// A MIDI file could not be parsed
+	Midi { path: String, reason: String },
+
+	/// A background image could not be opened or decoded
+	Image { path: String, reason: String },
+
+	/// The `--window-preset` argument doesn't match any preset
+	InvalidPreset(String),
+
+	/// One or more channels of a song failed to load
+	Channels(Vec<LoadError>),
+}
+
+impl fmt::Display for LoadError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			LoadError::Io { path, error } => {
+				write!(f, "Could not open \"{path}\" - Error: {error}")
+			}
+			LoadError::Config { path, reason } => {
+				write!(f, "Invalid config \"{path}\" - Error: {reason}")
+			}
+			LoadError::Audio { path, reason } => {
+				write!(f, "Could not load track \"{path}\" - Error: {reason}")
+			}
+			LoadError::Midi { path, reason } => {
+				write!(f, "Could not load MIDI file \"{path}\" - Error: {reason}")
+			}
+			LoadError::Image { path, reason } => {
+				write!(f, "Could not load image \"{path}\" - Error: {reason}")
+			}
+			LoadError::InvalidPreset(preset) => write!(
+				f,
+				"The preset \"{preset}\" does not exist - use '16x9', '9x16', or '9x18'."
+			),
+			LoadError::Channels(errors) => {
+				write!(f, "{} channel(s) failed to load:", errors.len())?;
+				for error in errors {
+					write!(f, "\n  {error}")?;
+				}
+				Ok(())
+			}
+		}
+	}
+}
+
+impl std::error::Error for LoadError {}
+
+impl LoadError {
+	pub fn io(path: &str, error: std::io::Error) -> Self {
+		LoadError::Io {
+			path: path.to_owned(),
+			error,
+		}
+	}
+
+	pub fn config(path: &str, reason: impl ToString) -> Self {
+		LoadError::Config {
+			path: path.to_owned(),
+			reason: reason.to_string(),
+		}
+	}
+
+	pub fn audio(path: &str, reason: impl ToString) -> Self {
+		LoadError::Audio {
+			path: path.to_owned(),
+			reason: reason.to_string(),
+		}
+	}
+
+	pub fn midi(path: &str, reason: impl ToString) -> Self {
+		LoadError::Midi {
+			path: path.to_owned(),
+			reason: reason.to_string(),
+		}
+	}
+
+	pub fn image(path: &str, reason: impl ToString) -> Self {
+		LoadError::Image {
+			path: path.to_owned(),
+			reason: reason.to_string(),
+		}
+	}
+}
+
+/// Anything that can go wrong while finishing the video file, once rendering has ended
+#[derive(Debug)]
+pub enum EncodeError {
+	/// The encoder could not finish writing the video
+	Finish { path: String, reason: String },
+
+	/// The master audio could not be muxed in. The video without audio is left at `path`.
+	Mux { path: String, reason: String },
+
+	/// The video-only render could not be removed after muxing
+	Cleanup { path: String, error: std::io::Error },
+}
+
+impl fmt::Display for EncodeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			EncodeError::Finish { path, reason } => {
+				write!(f, "Could not finish writing \"{path}\" - Error: {reason}")
+			}
+			EncodeError::Mux { path, reason } => write!(
+				f,
+				"Could not mux the master audio into the video - Error: {reason}\nThe video without audio was kept at \"{path}\""
+			),
+			EncodeError::Cleanup { path, error } => {
+				write!(f, "Could not remove the temporary video \"{path}\" - Error: {error}")
+			}
+		}
+	}
+}
+
+impl std::error::Error for EncodeError {}
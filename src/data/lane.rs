@@ -0,0 +1,112 @@
+use crate::{SCREEN_DURATION_SECS, SCREEN_FRAME_RATE};
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+#[derive(Deserialize, Clone, Copy, Default, Debug)]
+#[serde(default)]
+pub struct LaneSettings {
+	/// How many seconds fit across the lane. Defaults to the window's `duration_secs`
+	pub span_secs: Option<f64>,
+}
+
+impl LaneSettings {
+	fn span_secs(&self) -> f64 {
+		self.span_secs.unwrap_or(*SCREEN_DURATION_SECS)
+	}
+}
+
+/// The peak and RMS level of one video frame's worth of samples
+#[derive(Clone, Copy, Default)]
+pub struct Level {
+	pub peak: f32,
+	pub rms: f32,
+}
+
+impl Level {
+	fn measure(samples: &[f32]) -> Self {
+		if samples.is_empty() {
+			return Level::default();
+		}
+
+		let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+		let sum_squares: f32 = samples.iter().map(|s| s * s).sum();
+
+		Level {
+			peak,
+			rms: (sum_squares / samples.len() as f32).sqrt(),
+		}
+	}
+}
+
+/// A DAW-style lane, showing the level of the previous and next `span_secs / 2` seconds of a
+/// trace, with the playhead in the middle
+pub struct Lane {
+	/// How many samples each level covers, ie. one video frame
+	samples_per_level: f32,
+
+	/// How many levels are shown either side of the playhead
+	half_length: usize,
+
+	/// Levels for each frame that has already played, oldest first
+	history: VecDeque<Level>,
+
+	/// Levels for each frame that's coming up, starting at the playhead
+	pub upcoming: Vec<Level>,
+}
+
+impl Lane {
+	pub fn new(settings: LaneSettings, sample_rate: u32) -> Self {
+		// At least the current frame is always shown
+		let half_length =
+			((settings.span_secs() / 2.0) * SCREEN_FRAME_RATE.as_f64()).ceil() as usize;
+		let half_length = half_length.max(1);
+
+		Self {
+			samples_per_level: sample_rate as f32 / SCREEN_FRAME_RATE.as_f64() as f32,
+			half_length,
+			history: VecDeque::from(vec![Level::default(); half_length]),
+			upcoming: Vec::new(),
+		}
+	}
+
+	/// How many samples past the playhead are needed to fill the upcoming half of the lane
+	pub fn samples_ahead(settings: LaneSettings, sample_rate: u32) -> usize {
+		(settings.span_secs() / 2.0 * sample_rate as f64).ceil() as usize
+	}
+
+	/// Measures the samples coming up after the playhead, which should start with the current
+	/// frame, then moves the current frame into the history
+	pub fn update(&mut self, samples: &[f32]) {
+		self.upcoming = (0..self.half_length)
+			.map(|index| {
+				let start = (index as f32 * self.samples_per_level) as usize;
+				let end = ((index + 1) as f32 * self.samples_per_level) as usize;
+
+				Level::measure(&samples[start.min(samples.len())..end.min(samples.len())])
+			})
+			.collect();
+
+		self.history.pop_front();
+		self.history.push_back(self.upcoming[0]);
+	}
+
+	/// Combines the history and upcoming levels into `width` columns, taking the loudest level
+	/// in each column. The playhead is at the centre column.
+	pub fn columns(&self, width: u32) -> Vec<Level> {
+		let levels: Vec<&Level> = self.history.iter().chain(&self.upcoming[1..]).collect();
+
+		(0..width as usize)
+			.map(|x| {
+				let start = x * levels.len() / width as usize;
+				let end = ((x + 1) * levels.len() / width as usize).max(start + 1);
+
+				levels[start..end.min(levels.len())]
+					.iter()
+					.fold(Level::default(), |max, level| Level {
+						peak: max.peak.max(level.peak),
+						rms: max.rms.max(level.rms),
+					})
+			})
+			.collect()
+	}
+}
@@ -0,0 +1,268 @@
+use crate::{display::RGB, SCREEN_HEIGHT, SCREEN_WIDTH};
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(default)]
+pub struct LayoutSettings {
+	/// Defaults to two columns on landscape screens, and one otherwise
+	pub columns: Option<usize>,
+
+	/// The least number of rows. More are added when the channels don't fit.
+	pub rows: Option<usize>,
+
+	/// Space around the edge of the screen, in pixels
+	pub margin: u32,
+
+	/// Space between cells, in pixels
+	pub padding: u32,
+
+	pub separator: Separator,
+}
+
+/// A line along the bottom and right edges of each cell
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct Separator {
+	/// Defaults to the theme's separator colour
+	pub colour: Option<RGB>,
+
+	/// In pixels. 0 turns the separator off.
+	pub width: u32,
+}
+
+impl Default for Separator {
+	fn default() -> Self {
+		Self {
+			colour: None,
+			width: 1,
+		}
+	}
+}
+
+/// How many columns and rows of the grid a channel covers
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct Span {
+	pub columns: usize,
+	pub rows: usize,
+}
+
+impl Default for Span {
+	fn default() -> Self {
+		Self {
+			columns: 1,
+			rows: 1,
+		}
+	}
+}
+
+/// A length in pixels at the base resolution, or a percentage of the screen, eg. `"50%"`
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(try_from = "LengthConfig")]
+pub enum Length {
+	Pixels(f32),
+	Percent(f32),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LengthConfig {
+	Number(f32),
+	Text(String),
+}
+
+impl TryFrom<LengthConfig> for Length {
+	type Error = String;
+
+	fn try_from(value: LengthConfig) -> Result<Self, Self::Error> {
+		match value {
+			LengthConfig::Number(pixels) => Ok(Length::Pixels(pixels)),
+			LengthConfig::Text(text) => text
+				.strip_suffix('%')
+				.and_then(|number| number.trim().parse().ok())
+				.map(Length::Percent)
+				.ok_or_else(|| format!("Expected a percentage like \"50%\", found \"{}\"", text)),
+		}
+	}
+}
+
+impl Length {
+	/// The length in pixels, where percentages are of `screen_size`
+	pub fn resolve(&self, screen_size: u32) -> f32 {
+		match self {
+			Length::Pixels(pixels) => *pixels,
+			Length::Percent(percent) => screen_size as f32 * percent / 100.0,
+		}
+	}
+}
+
+/// A channel's place on screen, chosen in the config instead of the grid
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct RectConfig {
+	pub x: Length,
+	pub y: Length,
+	pub w: Length,
+	pub h: Length,
+}
+
+impl RectConfig {
+	pub fn to_rect(self) -> Rect {
+		Rect {
+			x: self.x.resolve(*SCREEN_WIDTH),
+			y: self.y.resolve(*SCREEN_HEIGHT),
+			width: self.w.resolve(*SCREEN_WIDTH),
+			height: self.h.resolve(*SCREEN_HEIGHT),
+		}
+	}
+}
+
+/// Where a channel is drawn on screen, in pixels at the base resolution. Positions aren't rounded,
+/// so cells can move smoothly between layouts.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Rect {
+	pub x: f32,
+	pub y: f32,
+	pub width: f32,
+	pub height: f32,
+}
+
+impl Rect {
+	/// A rect with no size, in the centre of this one, for a cell to shrink into
+	pub fn collapsed(&self) -> Rect {
+		Rect {
+			x: self.x + self.width / 2.0,
+			y: self.y + self.height / 2.0,
+			width: 0.0,
+			height: 0.0,
+		}
+	}
+
+	/// Moves `amount` of the way towards `target`, from 0.0 (not at all) to 1.0 (all the way)
+	pub fn approach(&self, target: &Rect, amount: f32) -> Rect {
+		let lerp = |from: f32, to: f32| from + (to - from) * amount;
+
+		Rect {
+			x: lerp(self.x, target.x),
+			y: lerp(self.y, target.y),
+			width: lerp(self.width, target.width),
+			height: lerp(self.height, target.height),
+		}
+	}
+
+	/// The left, top, right and bottom edges, rounded to whole pixels and clipped to the screen.
+	/// Negative sizes are treated as empty, so the right and bottom edges are never before the left
+	/// and top.
+	pub fn edges(&self) -> (u32, u32, u32, u32) {
+		let clip_x = |x: f32| x.round().clamp(0.0, *SCREEN_WIDTH as f32) as u32;
+		let clip_y = |y: f32| y.round().clamp(0.0, *SCREEN_HEIGHT as f32) as u32;
+		let (x1, y1) = (clip_x(self.x), clip_y(self.y));
+
+		(
+			x1,
+			y1,
+			clip_x(self.x + self.width).max(x1),
+			clip_y(self.y + self.height).max(y1),
+		)
+	}
+}
+
+/// Places each channel in the first space its `span` fits, from left to right then top to
+/// bottom. Every column and row is as big as the heaviest `weight` of the channels in it.
+pub fn grid(settings: &LayoutSettings, channels: &[(Span, f32)]) -> Vec<Rect> {
+	let columns = settings
+		.columns
+		.unwrap_or(if *SCREEN_WIDTH >= *SCREEN_HEIGHT {
+			2.min(channels.len())
+		} else {
+			1
+		})
+		.max(1);
+
+	// Which columns of each row are taken so far
+	let mut taken: Vec<Vec<bool>> = Vec::new();
+	let mut placements = Vec::with_capacity(channels.len());
+
+	for (span, _) in channels {
+		let width = span.columns.clamp(1, columns);
+		let height = span.rows.max(1);
+
+		let fits = |taken: &Vec<Vec<bool>>, col: usize, row: usize| {
+			(row..row + height).all(|row| {
+				taken
+					.get(row)
+					.is_none_or(|cols| cols[col..col + width].iter().all(|taken| !taken))
+			})
+		};
+
+		let (col, row) = (0..)
+			.flat_map(|row| (0..=columns - width).map(move |col| (col, row)))
+			.find(|&(col, row)| fits(&taken, col, row))
+			.unwrap();
+
+		if taken.len() < row + height {
+			taken.resize(row + height, vec![false; columns]);
+		}
+		for cols in &mut taken[row..row + height] {
+			cols[col..col + width].fill(true);
+		}
+
+		placements.push((col, row, width, height));
+	}
+
+	let rows = taken.len().max(settings.rows.unwrap_or(0)).max(1);
+
+	let mut column_weights = vec![0.0f32; columns];
+	let mut row_weights = vec![0.0f32; rows];
+	for ((col, row, width, height), (_, weight)) in placements.iter().zip(channels) {
+		for column_weight in &mut column_weights[*col..col + width] {
+			*column_weight = column_weight.max(*weight);
+		}
+		for row_weight in &mut row_weights[*row..row + height] {
+			*row_weight = row_weight.max(*weight);
+		}
+	}
+
+	let column_edges = track_edges(&column_weights, *SCREEN_WIDTH, settings);
+	let row_edges = track_edges(&row_weights, *SCREEN_HEIGHT, settings);
+
+	placements
+		.iter()
+		.map(|&(col, row, width, height)| {
+			let (x, _) = column_edges[col];
+			let (_, right) = column_edges[col + width - 1];
+			let (y, _) = row_edges[row];
+			let (_, bottom) = row_edges[row + height - 1];
+
+			Rect {
+				x,
+				y,
+				width: right - x,
+				height: bottom - y,
+			}
+		})
+		.collect()
+}
+
+/// The start and end of each column or row, sharing out the screen by weight. Empty columns and
+/// rows get a weight of 1.
+fn track_edges(weights: &[f32], screen_size: u32, settings: &LayoutSettings) -> Vec<(f32, f32)> {
+	let weights: Vec<f32> = weights
+		.iter()
+		.map(|&weight| if weight > 0.0 { weight } else { 1.0 })
+		.collect();
+	let total_weight: f32 = weights.iter().sum();
+
+	let gaps = settings.margin * 2 + settings.padding * (weights.len() as u32 - 1);
+	let available = screen_size.saturating_sub(gaps) as f32;
+
+	let mut start = settings.margin as f32;
+	weights
+		.iter()
+		.map(|weight| {
+			let size = available * weight / total_weight;
+			let edges = (start, start + size);
+			start += size + settings.padding as f32;
+			edges
+		})
+		.collect()
+}
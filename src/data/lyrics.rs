@@ -1,3 +1,5 @@
+use super::error::LoadError;
+
 #[derive(Debug)]
 pub struct LyricLine {
 	time_start: f64,
@@ -11,16 +13,12 @@ pub struct Lyrics {
 }
 
 impl Lyrics {
-	pub fn new(path: &Option<String>) -> Option<Self> {
-		if path.is_none() {
-			return None;
-		}
+	pub fn new(path: &Option<String>) -> Result<Option<Self>, LoadError> {
+		let Some(path) = path else {
+			return Ok(None);
+		};
 
-		let file = std::fs::read_to_string(path.as_ref().unwrap());
-		if file.is_err() {
-			panic!("Could not load lyrics file");
-		}
-		let file = file.unwrap();
+		let file = std::fs::read_to_string(path).map_err(|err| LoadError::io(path, err))?;
 		let lines_raw = file.lines();
 
 		let mut lines: Vec<LyricLine> = vec![];
@@ -46,7 +44,7 @@ impl Lyrics {
 			}
 		}
 
-		Some(Lyrics { lines })
+		Ok(Some(Lyrics { lines }))
 	}
 
 	pub fn find_line(&self, time: f64) -> Option<&str> {
@@ -1,7 +1,13 @@
 use super::{
+	background::BackgroundSettings,
 	channel::SongError,
-	defaults::{default_output, default_true},
+	defaults::{default_one, default_output, default_true},
+	error::LoadError,
+	layout::RectConfig,
 	lyrics::Lyrics,
+	mux,
+	style::GradientSettings,
+	theme::{Theme, ThemeConfig},
 	video::Encoding,
 };
 use crate::{
@@ -31,6 +37,8 @@ pub struct MidiChannel {
 	last_tick: u32,
 	pub colour: RGB,
 	pub notes: Vec<MidiNote>,
+	pub rect: Option<RectConfig>,
+	pub background_opacity: f32,
 }
 
 impl MidiChannel {
@@ -41,7 +49,9 @@ impl MidiChannel {
 			note_max: key.as_int(),
 			last_tick: delta,
 			notes: vec![],
-			colour: [24, 24, 24],
+			colour: Theme::default().midi_channel,
+			rect: None,
+			background_opacity: 1.0,
 		}
 	}
 
@@ -52,7 +62,9 @@ impl MidiChannel {
 			note_max: 0,
 			last_tick: 0,
 			notes: vec![],
-			colour: [24, 24, 24],
+			colour: Theme::default().midi_channel,
+			rect: None,
+			background_opacity: 1.0,
 		}
 	}
 }
@@ -70,11 +82,18 @@ pub struct MidiChannelConfig {
 	#[serde(default)]
 	pub order: usize,
 
-	#[serde(default)]
-	pub colour: RGB,
+	/// Defaults to the theme's channel colour
+	pub colour: Option<RGB>,
 
 	#[serde(default = "default_true")]
 	pub visible: bool,
+
+	/// Draw the channel here, instead of stacking it with the others
+	pub rect: Option<RectConfig>,
+
+	/// How see-through the channel's background is, from 0.0 (invisible) to 1.0 (solid)
+	#[serde(default = "default_one")]
+	pub background_opacity: f32,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -89,7 +108,25 @@ pub struct MidiSongConfig {
 	#[serde(default = "default_true")]
 	pub use_gradients: bool,
 
+	/// Only used when `use_gradients` is turned on
+	#[serde(default)]
+	pub gradient: GradientSettings,
+
 	pub lyrics_file: Option<String>,
+
+	#[serde(default)]
+	pub theme: ThemeConfig,
+
+	/// An image drawn behind the channels
+	pub background: Option<BackgroundSettings>,
+
+	/// Optional mixdown, muxed into the output video as an AAC stream
+	pub master_audio: Option<String>,
+
+	/// Only render part of the song, from `start_secs` until `end_secs`
+	#[serde(default)]
+	pub start_secs: f64,
+	pub end_secs: Option<f64>,
 }
 
 #[derive(Debug)]
@@ -104,39 +141,54 @@ pub struct MidiSong {
 	pub channels: HashMap<usize, MidiChannel>,
 	pub channels_vec: Vec<MidiChannel>,
 	pub lyrics: Option<Lyrics>,
+	pub colours: Theme,
+	pub background_image: Option<RgbImage>,
 }
 
 impl MidiSong {
-	pub fn new(smf: &Smf, config: MidiSongConfig) -> Self {
-		Self {
+	pub fn new(smf: &Smf, config: MidiSongConfig) -> Result<Self, LoadError> {
+		if let Some(master_audio) = &config.master_audio {
+			mux::probe_master_audio(master_audio)?;
+		}
+
+		let colours = config.theme.load()?;
+		let background_image = config
+			.background
+			.as_ref()
+			.map(|background| background.load(colours.background))
+			.transpose()?;
+
+		Ok(Self {
 			us_per_tick: 0.0,
-			ppq: MidiSong::get_ppq(smf),
+			ppq: MidiSong::get_ppq(smf).ok_or_else(|| {
+				LoadError::midi(&config.midi_file, "SMPTE timecode is not supported")
+			})?,
 			tempo: 0,
 			duration_ticks: 0,
-			playhead_secs: -(*SCREEN_DURATION_SECS / 2.0),
+			playhead_secs: config.start_secs - (*SCREEN_DURATION_SECS / 2.0),
 			seconds_per_frame: *SCREEN_DURATION_SECS,
 			channels: HashMap::new(),
 			channels_vec: Vec::with_capacity(16),
-			lyrics: Lyrics::new(&config.lyrics_file),
+			lyrics: Lyrics::new(&config.lyrics_file)?,
+			colours,
+			background_image,
 			config,
-		}
+		})
 	}
 
-	pub fn load_from_file(json_path: &str) -> Self {
-		let file = File::open(json_path);
-		if file.is_err() {
-			panic!("Could not open song.json");
-		}
-		let file = file.unwrap();
+	pub fn load_from_file(json_path: &str) -> Result<Self, LoadError> {
+		let file = File::open(json_path).map_err(|err| LoadError::io(json_path, err))?;
 
 		let rdr = BufReader::new(file);
-		let config: MidiSongConfig = serde_json::from_reader(rdr).unwrap();
+		let config: MidiSongConfig =
+			serde_json::from_reader(rdr).map_err(|err| LoadError::config(json_path, err))?;
 
 		MidiSong::generate_song_from_midi(config)
 	}
 
 	fn update_name(&mut self, channel: usize, name: &[u8]) {
-		let name_str = String::from_utf8(name.to_vec()).unwrap();
+		// Track names aren't always UTF-8, so anything else is replaced rather than failing the load
+		let name_str = String::from_utf8_lossy(name).into_owned();
 
 		if let Some(chan) = self.channels.get_mut(&channel) {
 			if chan.name.is_empty() {
@@ -174,7 +226,10 @@ impl MidiSong {
 	}
 
 	fn end_note(&mut self, channel: usize, key: u7, delta: u28) {
-		let chan = self.channels.get_mut(&channel).unwrap();
+		// A note-off without a matching note-on has nothing to end
+		let Some(chan) = self.channels.get_mut(&channel) else {
+			return;
+		};
 
 		chan.last_tick += delta.as_int();
 		if let Some(hanging_note) = chan
@@ -190,10 +245,10 @@ impl MidiSong {
 		}
 	}
 
-	fn get_ppq(smf: &Smf) -> u16 {
+	fn get_ppq(smf: &Smf) -> Option<u16> {
 		match smf.header.timing {
-			midly::Timing::Metrical(a) => a.as_int(),
-			midly::Timing::Timecode(_, _) => unimplemented!("Logic to figure out here..."),
+			midly::Timing::Metrical(a) => Some(a.as_int()),
+			midly::Timing::Timecode(_, _) => None,
 		}
 	}
 
@@ -206,6 +261,14 @@ impl MidiSong {
 		self.duration_ticks as f64 * (self.us_per_tick / 1_000_000.0)
 	}
 
+	/// The time the render stops, either chosen in the config or once the last note has scrolled
+	/// off screen
+	pub fn get_end_secs(&self) -> f64 {
+		self.config
+			.end_secs
+			.unwrap_or_else(|| self.get_song_duration() + (*SCREEN_DURATION_SECS / 2.0))
+	}
+
 	fn get_ticks_in_time_frame(&self) -> (u32, u32, i32, i32) {
 		let tick_start = ((self.playhead_secs * 1_000_000.0) / self.us_per_tick)
 			.clamp(0.0, self.duration_ticks as f64) as u32;
@@ -240,10 +303,11 @@ impl MidiSong {
 			.collect()
 	}
 
-	pub fn generate_song_from_midi(config: MidiSongConfig) -> MidiSong {
-		let data = std::fs::read(&config.midi_file).unwrap();
-		let smf = Smf::parse(&data).unwrap();
-		let mut song = MidiSong::new(&smf, config);
+	pub fn generate_song_from_midi(config: MidiSongConfig) -> Result<MidiSong, LoadError> {
+		let data = std::fs::read(&config.midi_file)
+			.map_err(|err| LoadError::io(&config.midi_file, err))?;
+		let smf = Smf::parse(&data).map_err(|err| LoadError::midi(&config.midi_file, err))?;
+		let mut song = MidiSong::new(&smf, config)?;
 
 		let mut channel_index = 0;
 		smf.tracks.iter().for_each(|track| {
@@ -280,11 +344,16 @@ impl MidiSong {
 						return channels;
 					}
 					let mut new_channel = channel.clone();
+					new_channel.colour = song.colours.midi_channel;
 					if let Some(config) = song.config.channels.get(&channel.name) {
 						if !config.visible {
 							return channels;
 						}
-						new_channel.colour = config.colour;
+						if let Some(colour) = config.colour {
+							new_channel.colour = colour;
+						}
+						new_channel.rect = config.rect;
+						new_channel.background_opacity = config.background_opacity;
 					}
 					channels.push(new_channel);
 					channels
@@ -309,42 +378,99 @@ impl MidiSong {
 			song.duration_ticks
 		);
 
-		song
+		Ok(song)
 	}
 
 	pub fn draw(&mut self, frame: &mut RgbImage, encoding: &mut Encoding) -> Result<(), SongError> {
-		let mut channel_height = *SCREEN_HEIGHT / self.channels_vec.len() as u32;
+		// Channels with their own rect aren't stacked with the others
+		let stacked = self
+			.channels_vec
+			.iter()
+			.filter(|channel| channel.rect.is_none())
+			.count()
+			.max(1) as u32;
+		let mut channel_height = *SCREEN_HEIGHT / stacked;
 		let channel_width = *SCREEN_WIDTH;
 
+		// Channels with their own rect don't cover the whole screen, and can be see-through
+		if let Some(background_image) = &self.background_image {
+			frame.copy_from_slice(background_image);
+		} else {
+			draw::rect(
+				frame,
+				0,
+				0,
+				*SCREEN_WIDTH,
+				*SCREEN_HEIGHT,
+				self.colours.background,
+			);
+		}
+
 		if let Some(lyrics) = &self.lyrics {
 			let y = *SCREEN_HEIGHT - 11;
-			channel_height = y / self.channels_vec.len() as u32;
-			draw::rect(frame, 0, y, *SCREEN_WIDTH, *SCREEN_HEIGHT, [0, 0, 0]);
+			channel_height = y / stacked;
+			draw::rect(
+				frame,
+				0,
+				y,
+				*SCREEN_WIDTH,
+				*SCREEN_HEIGHT,
+				self.colours.background,
+			);
 
 			if let Some(line) = lyrics.find_line(self.playhead_secs + (*SCREEN_DURATION_SECS / 2.0))
 			{
 				let x =
 					((*SCREEN_WIDTH - (line.len() as u32 * FONT_SEPARATION)) as f64 / 2.0) as u32;
-				draw::text(frame, x, y + 1, &line);
+				draw::text_colour(frame, x, y + 1, &line, self.colours.lyric);
 			}
 		}
 
-		let x_min = 0;
-		let x_min_f = x_min as f64;
-		let x_max = x_min + channel_width - 1;
-		let x_max_f = x_max as f64;
 		let mut row = 0;
 
 		for channel in &self.channels_vec {
-			let y_min = channel_height * row as u32;
+			let (x_min, y_min, x_max, y_max) = match &channel.rect {
+				Some(rect) => {
+					let (x1, y1, x2, y2) = rect.to_rect().edges();
+					(x1, y1, x2.saturating_sub(1), y2.saturating_sub(1))
+				}
+				None => {
+					let y_min = channel_height * row as u32;
+					row += 1;
+					(0, y_min, channel_width - 1, y_min + channel_height - 1)
+				}
+			};
+
+			// Too small to fit any notes
+			if x_max < x_min + 2 || y_max < y_min + 2 {
+				continue;
+			}
+
+			let x_min_f = x_min as f64;
+			let x_max_f = x_max as f64;
 			let y_min_f = y_min as f64;
-			let y_max = y_min + channel_height - 1;
 			let y_max_f = y_max as f64;
 
 			if self.config.use_gradients {
-				draw::rect_gradient(frame, x_min, y_min, x_max, y_max, channel.colour);
+				draw::rect_gradient(
+					frame,
+					x_min,
+					y_min,
+					x_max,
+					y_max,
+					&self.config.gradient.for_colour(channel.colour),
+					channel.background_opacity,
+				);
 			} else {
-				draw::rect(frame, x_min, y_min, x_max, y_max, channel.colour);
+				draw::rect_blend(
+					frame,
+					x_min,
+					y_min,
+					x_max,
+					y_max,
+					channel.colour,
+					channel.background_opacity,
+				);
 			}
 
 			let (tick_start, tick_end, ts_raw, te_raw) = self.get_ticks_in_time_frame();
@@ -389,23 +515,40 @@ impl MidiSong {
 					y1_min + 1,
 					x2_min + 1,
 					y2_min + 1,
-					[0, 0, 0],
+					self.colours.shadow,
+				);
+				draw::rect(
+					frame,
+					x1_min,
+					y1_min,
+					x2_min,
+					y2_min,
+					self.colours.foreground,
 				);
-				draw::rect(frame, x1_min, y1_min, x2_min, y2_min, [255, 255, 255]);
 			}
 
-			draw::text_colour(frame, x_min + 5, y_min + 5, &channel.name, [0, 0, 0]);
-			draw::text(frame, x_min + 4, y_min + 4, &channel.name);
-
-			row += 1;
+			draw::text_colour(
+				frame,
+				x_min + 5,
+				y_min + 5,
+				&channel.name,
+				self.colours.shadow,
+			);
+			draw::text_colour(
+				frame,
+				x_min + 4,
+				y_min + 4,
+				&channel.name,
+				self.colours.label,
+			);
 		}
 
 		// Render frame to video
 		encoding.render_frame(frame);
 
-		self.playhead_secs += 1.0 / *SCREEN_FRAME_RATE as f64;
+		self.playhead_secs += 1.0 / SCREEN_FRAME_RATE.as_f64();
 
-		if self.playhead_secs >= self.get_song_duration() {
+		if self.playhead_secs + (*SCREEN_DURATION_SECS / 2.0) >= self.get_end_secs() {
 			return Err(SongError::End);
 		}
 
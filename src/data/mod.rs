@@ -1,8 +1,20 @@
+pub mod alignment;
+pub mod background;
 pub mod channel;
 pub mod cli;
 pub mod defaults;
+pub mod error;
+pub mod lane;
+pub mod layout;
 pub mod lyrics;
 pub mod midi;
+pub mod mux;
+pub mod scope;
+pub mod silence;
 pub mod song;
+pub mod spectrum;
+pub mod stream;
+pub mod style;
+pub mod theme;
 pub mod video;
 pub mod window;
@@ -0,0 +1,315 @@
+use super::error::LoadError;
+use ffmpeg_next::{
+	codec::{self, capabilities::Capabilities},
+	encoder, filter, format, frame, media, ChannelLayout, Error, Packet, Rational,
+};
+use std::fs::File;
+use video_rs::Time;
+
+/// Checks that `audio_path` has an audio stream ffmpeg can decode, so a bad master audio file is
+/// reported before rendering rather than when it's muxed in at the end
+pub fn probe_master_audio(audio_path: &str) -> Result<(), LoadError> {
+	File::open(audio_path).map_err(|err| LoadError::io(audio_path, err))?;
+
+	let probe = || -> Result<(), Error> {
+		let audio_ctx = format::input(&audio_path)?;
+		let input = audio_ctx
+			.streams()
+			.best(media::Type::Audio)
+			.ok_or(Error::StreamNotFound)?;
+		codec::context::Context::from_parameters(input.parameters())?
+			.decoder()
+			.audio()?;
+
+		Ok(())
+	};
+
+	probe().map_err(|err| LoadError::audio(audio_path, format!("Unusable master audio ({err})")))
+}
+
+/// Copies the rendered H.264 stream from `video_path` into `output_path`, alongside the
+/// `audio_path` master audio re-encoded as AAC. The audio is trimmed to start at `start_secs` and
+/// last for `duration`, which should be the final position of the video encoder, so both streams
+/// start and end together.
+pub fn mux_master_audio(
+	video_path: &str,
+	audio_path: &str,
+	output_path: &str,
+	start_secs: f64,
+	duration: Time,
+) -> Result<(), Error> {
+	let mut video_ctx = format::input(&video_path)?;
+	let mut audio_ctx = format::input(&audio_path)?;
+	let mut output_ctx = format::output(&output_path)?;
+
+	// Video: stream copy, no re-encoding
+	let video_in = video_ctx
+		.streams()
+		.best(media::Type::Video)
+		.ok_or(Error::StreamNotFound)?;
+	let video_in_index = video_in.index();
+	let video_in_time_base = video_in.time_base();
+	let video_out_index = {
+		let mut stream = output_ctx.add_stream(encoder::find(codec::Id::None))?;
+		stream.set_parameters(video_in.parameters());
+		// Let the MP4 muxer pick its own codec tag
+		unsafe {
+			(*stream.parameters().as_mut_ptr()).codec_tag = 0;
+		}
+		stream.index()
+	};
+
+	// Audio: decode, trim, and re-encode
+	let mut audio = AudioTranscoder::new(&mut audio_ctx, &mut output_ctx, start_secs, duration)?;
+
+	output_ctx.write_header()?;
+
+	// The muxer is free to change stream time bases when writing the header
+	let video_out_time_base = output_ctx.stream(video_out_index).unwrap().time_base();
+	audio.out_time_base = output_ctx.stream(audio.out_index).unwrap().time_base();
+
+	let audio_in_index = audio.in_index;
+	let mut audio_packets = audio_ctx
+		.packets()
+		.filter(|(stream, _)| stream.index() == audio_in_index);
+
+	for (stream, mut packet) in video_ctx.packets() {
+		if stream.index() != video_in_index {
+			continue;
+		}
+
+		// Keep the audio level with the video, so the muxer can interleave both streams
+		let video_secs = packet.pts().unwrap_or(0) as f64 * f64::from(video_in_time_base);
+		while audio.decoded_secs() < video_secs {
+			let Some((_, audio_packet)) = audio_packets.next() else {
+				break;
+			};
+
+			audio.decoder.send_packet(&audio_packet)?;
+			audio.receive_decoded_frames(&mut output_ctx)?;
+		}
+
+		packet.rescale_ts(video_in_time_base, video_out_time_base);
+		packet.set_position(-1);
+		packet.set_stream(video_out_index);
+		packet.write_interleaved(&mut output_ctx)?;
+	}
+
+	for (_, packet) in audio_packets {
+		audio.decoder.send_packet(&packet)?;
+		audio.receive_decoded_frames(&mut output_ctx)?;
+	}
+
+	audio.decoder.send_eof()?;
+	audio.receive_decoded_frames(&mut output_ctx)?;
+
+	audio.filter.get("in").unwrap().source().flush()?;
+	audio.receive_filtered_frames(&mut output_ctx)?;
+
+	audio.encoder.send_eof()?;
+	audio.receive_encoded_packets(&mut output_ctx)?;
+
+	output_ctx.write_trailer()?;
+
+	Ok(())
+}
+
+struct AudioTranscoder {
+	in_index: usize,
+	out_index: usize,
+	out_time_base: Rational,
+	decoder: codec::decoder::Audio,
+	encoder: codec::encoder::Audio,
+	filter: filter::Graph,
+
+	/// Running sample count, used as the timestamp for frames entering and leaving the filter
+	start_sample: i64,
+	samples_in: i64,
+	samples_out: i64,
+}
+
+impl AudioTranscoder {
+	fn new(
+		input_ctx: &mut format::context::Input,
+		output_ctx: &mut format::context::Output,
+		start_secs: f64,
+		duration: Time,
+	) -> Result<Self, Error> {
+		let input = input_ctx
+			.streams()
+			.best(media::Type::Audio)
+			.ok_or(Error::StreamNotFound)?;
+		let context = codec::context::Context::from_parameters(input.parameters())?;
+		let decoder = context.decoder().audio()?;
+
+		let codec = encoder::find(codec::Id::AAC)
+			.ok_or(Error::EncoderNotFound)?
+			.audio()?;
+		let global_header = output_ctx
+			.format()
+			.flags()
+			.contains(format::flag::Flags::GLOBAL_HEADER);
+
+		let mut output = output_ctx.add_stream(codec)?;
+		let context = codec::context::Context::from_parameters(output.parameters())?;
+		let mut encoder = context.encoder().audio()?;
+
+		if global_header {
+			encoder.set_flags(codec::flag::Flags::GLOBAL_HEADER);
+		}
+
+		let channel_layout = if decoder.channels() == 1 {
+			ChannelLayout::MONO
+		} else {
+			ChannelLayout::STEREO
+		};
+
+		encoder.set_rate(decoder.rate() as i32);
+		encoder.set_channel_layout(channel_layout);
+		encoder.set_format(codec.formats().ok_or(Error::InvalidData)?.next().unwrap());
+		encoder.set_bit_rate(192_000);
+		encoder.set_time_base((1, decoder.rate() as i32));
+		output.set_time_base((1, decoder.rate() as i32));
+
+		let encoder = encoder.open_as(codec)?;
+		output.set_parameters(&encoder);
+
+		let out_index = output.index();
+		let out_time_base = output.time_base();
+		let start_sample = (start_secs * decoder.rate() as f64).round() as i64;
+		let end_sample =
+			start_sample + (duration.as_secs_f64() * decoder.rate() as f64).round() as i64;
+		let filter = AudioTranscoder::filter(&decoder, &encoder, start_sample, end_sample)?;
+
+		Ok(Self {
+			in_index: input.index(),
+			out_index,
+			out_time_base,
+			decoder,
+			encoder,
+			filter,
+			start_sample,
+			samples_in: 0,
+			samples_out: 0,
+		})
+	}
+
+	/// Converts decoded audio into the encoder's sample format, and drops everything outside of
+	/// `start_sample` and `end_sample` so the audio lines up with the video.
+	fn filter(
+		decoder: &codec::decoder::Audio,
+		encoder: &codec::encoder::Audio,
+		start_sample: i64,
+		end_sample: i64,
+	) -> Result<filter::Graph, Error> {
+		let mut graph = filter::Graph::new();
+
+		// Some formats (eg. WAV) don't declare a channel layout, so guess one from the count
+		let channel_layout = if decoder.channel_layout().is_empty() {
+			ChannelLayout::default(decoder.channels() as i32)
+		} else {
+			decoder.channel_layout()
+		};
+
+		let args = format!(
+			"time_base=1/{}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+			decoder.rate(),
+			decoder.rate(),
+			decoder.format().name(),
+			channel_layout.bits()
+		);
+
+		graph.add(&filter::find("abuffer").unwrap(), "in", &args)?;
+		graph.add(&filter::find("abuffersink").unwrap(), "out", "")?;
+
+		{
+			let mut out = graph.get("out").unwrap();
+			out.set_sample_format(encoder.format());
+			out.set_channel_layout(encoder.channel_layout());
+			out.set_sample_rate(encoder.rate());
+		}
+
+		graph.output("in", 0)?.input("out", 0)?.parse(&format!(
+			"atrim=start_sample={start_sample}:end_sample={end_sample}"
+		))?;
+		graph.validate()?;
+
+		// AAC only accepts fixed-size frames
+		let variable_frame_size = encoder
+			.codec()
+			.map(|codec| {
+				codec
+					.capabilities()
+					.contains(Capabilities::VARIABLE_FRAME_SIZE)
+			})
+			.unwrap_or(false);
+
+		if !variable_frame_size {
+			graph
+				.get("out")
+				.unwrap()
+				.sink()
+				.set_frame_size(encoder.frame_size());
+		}
+
+		Ok(graph)
+	}
+
+	/// How much audio has been decoded, relative to the start of the video
+	fn decoded_secs(&self) -> f64 {
+		(self.samples_in - self.start_sample) as f64 / self.decoder.rate() as f64
+	}
+
+	fn receive_decoded_frames(
+		&mut self,
+		output_ctx: &mut format::context::Output,
+	) -> Result<(), Error> {
+		let mut decoded = frame::Audio::empty();
+		while self.decoder.receive_frame(&mut decoded).is_ok() {
+			decoded.set_pts(Some(self.samples_in));
+			self.samples_in += decoded.samples() as i64;
+
+			self.filter.get("in").unwrap().source().add(&decoded)?;
+			self.receive_filtered_frames(output_ctx)?;
+		}
+
+		Ok(())
+	}
+
+	fn receive_filtered_frames(
+		&mut self,
+		output_ctx: &mut format::context::Output,
+	) -> Result<(), Error> {
+		let mut filtered = frame::Audio::empty();
+		while self
+			.filter
+			.get("out")
+			.unwrap()
+			.sink()
+			.frame(&mut filtered)
+			.is_ok()
+		{
+			filtered.set_pts(Some(self.samples_out));
+			self.samples_out += filtered.samples() as i64;
+
+			self.encoder.send_frame(&filtered)?;
+			self.receive_encoded_packets(output_ctx)?;
+		}
+
+		Ok(())
+	}
+
+	fn receive_encoded_packets(
+		&mut self,
+		output_ctx: &mut format::context::Output,
+	) -> Result<(), Error> {
+		let mut encoded = Packet::empty();
+		while self.encoder.receive_packet(&mut encoded).is_ok() {
+			encoded.set_stream(self.out_index);
+			encoded.rescale_ts((1, self.encoder.rate() as i32), self.out_time_base);
+			encoded.write_interleaved(output_ctx)?;
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,91 @@
+use crate::SCREEN_FRAME_RATE;
+use serde::Deserialize;
+
+/// How bright a faded trace has to be before it disappears completely
+const FADED: f32 = 0.05;
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct XySettings {
+	/// How long the trace keeps glowing after it's drawn, in seconds
+	pub persistence_secs: f32,
+}
+
+impl Default for XySettings {
+	fn default() -> Self {
+		Self {
+			persistence_secs: 0.1,
+		}
+	}
+}
+
+/// The glow left behind on the screen of an XY plot, like the phosphor of an analogue scope
+pub struct Phosphor {
+	/// How much of the brightness is kept each frame
+	fade: f32,
+
+	pub size: u32,
+
+	/// Brightness of each pixel, from 0.0 to 1.0, in rows
+	pub intensity: Vec<f32>,
+}
+
+impl Phosphor {
+	pub fn new(settings: XySettings) -> Self {
+		let frames = settings.persistence_secs * SCREEN_FRAME_RATE.as_f64() as f32;
+		let fade = if frames > 0.0 {
+			FADED.powf(1.0 / frames)
+		} else {
+			0.0
+		};
+
+		Self {
+			fade,
+			size: 0,
+			intensity: Vec::new(),
+		}
+	}
+
+	/// Fades the previous frames, then traces the left channel on the X axis against the right
+	/// channel on the Y axis, in a square `size` pixels wide
+	pub fn update(&mut self, left: &[f32], right: &[f32], size: u32) {
+		if self.size != size {
+			self.size = size;
+			self.intensity = vec![0.0; (size * size) as usize];
+		}
+
+		for intensity in &mut self.intensity {
+			*intensity *= self.fade;
+		}
+
+		if size == 0 {
+			return;
+		}
+
+		let to_point = |(x, y): (&f32, &f32)| {
+			let scale = (size - 1) as f32 / 2.0;
+			(
+				(x.clamp(-1.0, 1.0) + 1.0) * scale,
+				(1.0 - y.clamp(-1.0, 1.0)) * scale,
+			)
+		};
+
+		let points: Vec<(f32, f32)> = left.iter().zip(right).map(to_point).collect();
+		for line in points.windows(2) {
+			self.line(line[0], line[1]);
+		}
+	}
+
+	/// Lights up every pixel between two points
+	fn line(&mut self, (x1, y1): (f32, f32), (x2, y2): (f32, f32)) {
+		let steps = (x2 - x1).abs().max((y2 - y1).abs()).ceil().max(1.0) as usize;
+
+		for step in 0..=steps {
+			let t = step as f32 / steps as f32;
+			let x = (x1 + (x2 - x1) * t).round() as u32;
+			let y = (y1 + (y2 - y1) * t).round() as u32;
+
+			self.intensity[(y * self.size + x) as usize] = 1.0;
+		}
+	}
+}
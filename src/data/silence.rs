@@ -0,0 +1,100 @@
+use crate::SCREEN_FRAME_RATE;
+use serde::Deserialize;
+
+/// How long a channel takes to dim or collapse when it falls silent, and to come back, in seconds
+pub const TRANSITION_SECS: f32 = 0.25;
+
+/// How dark a silent channel becomes when it's dimmed, from 0.0 (not at all) to 1.0 (black)
+const DIM_OPACITY: f32 = 0.75;
+
+/// What happens to a channel's cell once it has been silent for long enough
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SilenceAction {
+	/// Fade the cell towards black
+	#[default]
+	Dim,
+
+	/// Shrink the cell away, so the other channels grow to fill the space
+	Collapse,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct SilenceSettings {
+	/// Frames with a peak level below this count as silent, from 0.0 to 1.0
+	pub threshold: f32,
+
+	/// How long the channel has to stay silent before it's dimmed or collapsed
+	pub hold_secs: f64,
+
+	pub action: SilenceAction,
+}
+
+impl Default for SilenceSettings {
+	fn default() -> Self {
+		Self {
+			threshold: 0.01,
+			hold_secs: 1.0,
+			action: SilenceAction::default(),
+		}
+	}
+}
+
+/// Keeps track of how long a channel has been silent, and fades it in and out
+pub struct SilenceDetector {
+	settings: SilenceSettings,
+	silent_secs: f64,
+
+	/// How present the channel is, from 0.0 (silent) to 1.0 (playing)
+	presence: f32,
+}
+
+impl SilenceDetector {
+	pub fn new(settings: SilenceSettings) -> Self {
+		Self {
+			settings,
+			silent_secs: 0.0,
+			presence: 1.0,
+		}
+	}
+
+	/// Measures the first `frame_len` samples of each trace, which are the ones played this frame,
+	/// and moves the transition on by one frame
+	pub fn update(&mut self, traces: &[Vec<f32>], frame_len: usize) {
+		let peak = traces
+			.iter()
+			.flat_map(|trace| &trace[..frame_len.min(trace.len())])
+			.fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+
+		if peak < self.settings.threshold {
+			self.silent_secs += 1.0 / SCREEN_FRAME_RATE.as_f64();
+		} else {
+			self.silent_secs = 0.0;
+		}
+
+		let step = 1.0 / (TRANSITION_SECS * SCREEN_FRAME_RATE.as_f64() as f32);
+		self.presence = if self.is_silent() {
+			(self.presence - step).max(0.0)
+		} else {
+			(self.presence + step).min(1.0)
+		};
+	}
+
+	pub fn is_silent(&self) -> bool {
+		self.silent_secs >= self.settings.hold_secs
+	}
+
+	/// Whether the channel should give up its space to the others
+	pub fn is_collapsed(&self) -> bool {
+		self.settings.action == SilenceAction::Collapse && self.is_silent()
+	}
+
+	/// How much black to blend over the cell, from 0.0 to 1.0
+	pub fn dim_opacity(&self) -> f32 {
+		match self.settings.action {
+			SilenceAction::Dim => (1.0 - self.presence) * DIM_OPACITY,
+			SilenceAction::Collapse => 0.0,
+		}
+	}
+}
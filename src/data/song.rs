@@ -1,14 +1,28 @@
 use super::{
+	background::BackgroundSettings,
 	channel::{Channel, SongError},
 	defaults::{default_output, default_true},
+	error::LoadError,
+	lane::Lane,
+	layout::{self, LayoutSettings, Rect},
+	mux,
+	silence::TRANSITION_SECS,
+	style::{DisplayMode, GradientSettings, WaveformShape, WaveformStyle},
+	theme::{Theme, ThemeConfig},
 	video::Encoding,
 };
-use crate::{display::draw, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::{
+	display::{draw, RGB},
+	SCREEN_FRAME_RATE, SCREEN_HEIGHT, SCREEN_SCALE, SCREEN_SUPERSAMPLE, SCREEN_WIDTH,
+};
 use image::RgbImage;
 use rayon::prelude::*;
 use serde::Deserialize;
 use std::{fs::File, io::BufReader};
 
+/// How much of the distance to its new place a cell has left to move after `TRANSITION_SECS`
+const TRANSITION_EASE: f32 = 0.01;
+
 #[derive(Deserialize)]
 pub struct Song {
 	pub channels: Vec<Channel>,
@@ -18,23 +32,74 @@ pub struct Song {
 
 	#[serde(default = "default_true")]
 	pub use_gradients: bool,
+
+	/// Only used when `use_gradients` is turned on
+	#[serde(default)]
+	pub gradient: GradientSettings,
+
+	#[serde(default)]
+	pub layout: LayoutSettings,
+
+	#[serde(default)]
+	pub theme: ThemeConfig,
+
+	/// An image drawn behind the channels
+	pub background: Option<BackgroundSettings>,
+
+	#[serde(skip)]
+	pub background_image: Option<RgbImage>,
+
+	#[serde(skip)]
+	pub colours: Theme,
+
+	/// Optional mixdown, muxed into the output video as an AAC stream
+	pub master_audio: Option<String>,
+
+	/// Only render part of the song, from `start_secs` until `end_secs`
+	#[serde(default)]
+	pub start_secs: f64,
+	pub end_secs: Option<f64>,
+
+	#[serde(skip)]
+	pub playhead_secs: f64,
+
+	/// The frame at the output resolution, when waveforms are supersampled
+	#[serde(skip)]
+	pub scaled_frame: Option<RgbImage>,
+
+	/// Where each channel is currently drawn, moving between layouts as channels collapse
+	#[serde(skip)]
+	pub cells: Vec<Rect>,
 }
 
 impl Song {
-	pub fn load_from_file(song_path: &str) -> Self {
-		let file = File::open(song_path);
-		if file.is_err() {
-			panic!("Could not open song.json");
-		}
-		let file = file.unwrap();
+	pub fn load_from_file(song_path: &str) -> Result<Self, LoadError> {
+		let file = File::open(song_path).map_err(|err| LoadError::io(song_path, err))?;
 
 		let rdr = BufReader::new(file);
-		let mut song: Song = serde_json::from_reader(rdr).unwrap();
+		let mut song: Song =
+			serde_json::from_reader(rdr).map_err(|err| LoadError::config(song_path, err))?;
+
+		if song.channels.is_empty() {
+			return Err(LoadError::config(
+				song_path,
+				"Please provide at least one channel",
+			));
+		}
 
-		assert!(
-			song.channels.len() > 0,
-			"Please provide at least one channel"
-		);
+		if let Some(master_audio) = &song.master_audio {
+			mux::probe_master_audio(master_audio)?;
+		}
+
+		song.colours = song.theme.load()?;
+		song.background_image = song
+			.background
+			.as_ref()
+			.map(|background| background.load(song.colours.background))
+			.transpose()?;
+		for channel in &mut song.channels {
+			channel.apply_theme(&song.colours);
+		}
 
 		println!("Loaded song with {} channels", song.channels.len());
 
@@ -49,147 +114,444 @@ impl Song {
 			println!("{:<16} {}", display_name, channel.file);
 		}
 
-		song.load_tracks_into_memory();
+		song.load_tracks_into_memory()?;
+
+		if *SCREEN_SUPERSAMPLE {
+			song.scaled_frame = Some(RgbImage::new(
+				*SCREEN_WIDTH * *SCREEN_SCALE,
+				*SCREEN_HEIGHT * *SCREEN_SCALE,
+			));
+		}
 
-		song
+		Ok(song)
 	}
 
-	pub fn load_tracks_into_memory(&mut self) {
+	/// Loads every channel, reporting all of the channels that failed rather than just the first
+	pub fn load_tracks_into_memory(&mut self) -> Result<(), LoadError> {
+		let errors: Vec<LoadError> = self
+			.channels
+			.iter_mut()
+			.filter_map(|channel| {
+				channel
+					.load_track_into_memory()
+					.and_then(|_| channel.seek(self.start_secs))
+					.err()
+			})
+			.collect();
+
+		if !errors.is_empty() {
+			return Err(LoadError::Channels(errors));
+		}
+
 		for channel in &mut self.channels {
-			channel.load_track_into_memory();
+			channel.start_decoding();
 		}
+
+		self.playhead_secs = self.start_secs;
+
+		Ok(())
+	}
+
+	/// The time the render stops, either chosen in the config or at the end of the longest channel
+	pub fn get_end_secs(&self) -> f64 {
+		self.end_secs.unwrap_or_else(|| {
+			self.channels
+				.iter()
+				.map(|channel| channel.get_duration_secs())
+				.fold(0.0, f64::max)
+		})
 	}
 
 	pub fn draw(&mut self, frame: &mut RgbImage, encoding: &mut Encoding) -> Result<(), SongError> {
-		let cols = if *SCREEN_WIDTH >= *SCREEN_HEIGHT {
-			2.min(self.channels.len())
+		// Every channel moves on by a frame, even while it's collapsed
+		let mut channel_traces = Vec::with_capacity(self.channels.len());
+		for channel in &mut self.channels {
+			let (traces, frame_len) = channel.get_frame_samples()?;
+
+			if let Some(detector) = channel.silence_detector.as_mut() {
+				detector.update(&traces, frame_len);
+			}
+
+			channel_traces.push(traces);
+		}
+
+		self.update_cells();
+
+		// Cells don't always cover the whole screen, and can be see-through
+		if let Some(background_image) = &self.background_image {
+			frame.copy_from_slice(background_image);
 		} else {
-			1
-		};
+			draw::rect(
+				frame,
+				0,
+				0,
+				*SCREEN_WIDTH,
+				*SCREEN_HEIGHT,
+				self.colours.background,
+			);
+		}
+		if let Some(scaled_frame) = self.scaled_frame.as_mut() {
+			draw::upscale(
+				frame,
+				scaled_frame,
+				0,
+				0,
+				*SCREEN_WIDTH,
+				*SCREEN_HEIGHT,
+				*SCREEN_SCALE,
+			);
+		}
 
-		let rows = self.channels.chunks_mut(cols);
+		for (index, traces) in channel_traces.iter().enumerate() {
+			self.draw_channel(frame, index, traces);
+		}
 
-		let channel_height = *SCREEN_HEIGHT / rows.len() as u32;
-		let channel_width = *SCREEN_WIDTH / cols as u32;
+		// Render frame to video
+		if let Some(scaled_frame) = &self.scaled_frame {
+			encoding.render_scaled_frame(scaled_frame);
+		} else {
+			encoding.render_frame(frame);
+		}
 
-		for (row, chunks) in rows.enumerate() {
-			let y_offset = channel_height * row as u32;
+		self.playhead_secs += 1.0 / SCREEN_FRAME_RATE.as_f64();
 
-			for (col, channel) in chunks.iter_mut().enumerate() {
-				let x_offset = channel_width * col as u32;
+		if self.playhead_secs >= self.get_end_secs() {
+			return Err(SongError::End);
+		}
 
-				// Background Colour
-				draw::rect(
-					frame,
-					x_offset,
-					y_offset + channel_height - 1,
-					x_offset + channel_width - 1,
-					y_offset + channel_height,
-					[0, 0, 0],
-				);
+		Ok(())
+	}
+}
 
-				if self.use_gradients {
-					draw::rect_gradient(
-						frame,
-						x_offset,
-						y_offset,
-						x_offset + channel_width - 1,
-						y_offset + channel_height - 1,
-						channel.colour,
-					);
-				} else {
-					draw::rect(
-						frame,
-						x_offset,
-						y_offset,
-						x_offset + channel_width - 1,
-						y_offset + channel_height - 1,
-						channel.colour,
-					);
-				}
+impl Song {
+	/// Moves each cell towards its place in the layout. Collapsed channels are left out of the
+	/// grid, so the others reflow to fill the screen, unless every channel in it is collapsed.
+	fn update_cells(&mut self) {
+		let in_grid: Vec<usize> = (0..self.channels.len())
+			.filter(|&index| self.channels[index].rect.is_none())
+			.collect();
+		let present: Vec<usize> = in_grid
+			.iter()
+			.copied()
+			.filter(|&index| !self.channels[index].is_collapsed())
+			.collect();
+		let present = if present.is_empty() { in_grid } else { present };
+
+		let spans: Vec<_> = present
+			.iter()
+			.map(|&index| (self.channels[index].span, self.channels[index].weight))
+			.collect();
+
+		let mut targets: Vec<Option<Rect>> = vec![None; self.channels.len()];
+		for (index, rect) in present.iter().zip(layout::grid(&self.layout, &spans)) {
+			targets[*index] = Some(rect);
+		}
 
-				// Channel Name
-				draw::text(frame, x_offset + 4, y_offset + 4, &channel.name);
+		for (target, channel) in targets.iter_mut().zip(&self.channels) {
+			if let Some(rect) = channel.rect.filter(|_| !channel.is_collapsed()) {
+				*target = Some(rect.to_rect());
+			}
+		}
 
-				// Draw samples
-				let raw_samples = channel.get_frame_samples();
+		// The first frame starts in place
+		if self.cells.is_empty() {
+			self.cells = targets
+				.iter()
+				.map(|target| target.unwrap_or_default())
+				.collect();
+		}
 
-				if let Err(err) = raw_samples {
-					return Err(err);
-				}
+		// Each frame covers the same fraction of the remaining distance, so cells ease in
+		let amount =
+			1.0 - TRANSITION_EASE.powf(1.0 / (TRANSITION_SECS * SCREEN_FRAME_RATE.as_f64() as f32));
+		for (cell, target) in self.cells.iter_mut().zip(targets) {
+			let target = target.unwrap_or_else(|| cell.collapsed());
+			*cell = cell.approach(&target, amount);
+		}
+	}
 
-				let raw_samples = raw_samples.unwrap();
+	fn draw_channel(&mut self, frame: &mut RgbImage, index: usize, traces: &[Vec<f32>]) {
+		let (x1, y1, x2, y2) = self.cells[index].edges();
+		let channel_width = x2 - x1;
+		let channel_height = y2 - y1;
+		let trace_height = channel_height / traces.len() as u32;
 
-				// Determine a good start sample
-				// Loop through the first ~6% of samples and find a significant jump in the signal
-				let search_sample_max = raw_samples.len() / 15;
-				let mut start_sample = 0;
+		let separator = self.layout.separator;
+		if channel_width <= separator.width + 1 || trace_height <= separator.width + 1 {
+			return;
+		}
 
-				if channel.use_alignment {
-					for x in 0..search_sample_max {
-						let y_previous = raw_samples[x] as i16;
-						let y_current = raw_samples[x + 1] as i16;
-						let diff = y_previous - y_current;
+		let (x_offset, y_offset) = (x1, y1);
+		let channel = &mut self.channels[index];
+		let separator_colour = separator.colour.unwrap_or(self.colours.separator);
 
-						if diff >= 8 {
-							start_sample = x;
-							break;
-						}
-					}
-				}
+		// Both are filled in from the theme when the song is loaded
+		let background = channel.colour.unwrap_or_default();
+		let colour = channel.waveform_style.colour.unwrap_or_default();
 
-				// Resample raw vector by lerping between adjacent samples
-				let samples: Vec<u8> = (0..channel_width)
-					.into_par_iter()
-					.map(|index| {
-						let percent = (index as f32 / channel_width as f32)
-							* (raw_samples.len() - search_sample_max) as f32;
-						let remainder = percent % 1.0;
-						let i_low = percent.floor() as usize;
-						let i_high = percent.ceil() as usize;
-
-						// Lerp equation: (1 - t) * v0 + t * v1;
-						let value = (1.0 - remainder) * raw_samples[start_sample + i_low] as f32
-							+ remainder * raw_samples[start_sample + i_high] as f32;
-
-						value as u8
-					})
-					.collect();
-
-				for (x, sample) in samples.iter().enumerate() {
-					if x == 0 {
-						continue;
-					}
-
-					let x_position = x_offset + x as u32;
-
-					let mut y_previous = (samples[x - 1] as u32 * channel_height) / 256;
-					let mut y_current = (*sample as u32 * channel_height) / 256;
-
-					// Swap samples so it's always drawing downwards
-					if y_previous > y_current {
-						(y_current, y_previous) = (y_previous, y_current);
-					}
-
-					// Connect a line to the previous sample
-					draw::rect(
-						frame,
-						x_position - 1,
-						y_offset + y_previous,
-						x_position,
-						y_offset + y_current,
-						[255, 255, 255],
-					);
+		// Supersampled cells are drawn straight onto the scaled frame, which already holds a scaled
+		// up copy of the background, so channels underneath this one are kept
+		let (target, scale) = match self.scaled_frame.as_mut() {
+			Some(scaled_frame) => (scaled_frame, *SCREEN_SCALE),
+			None => (&mut *frame, 1),
+		};
 
-					// Draw the current sample
-					draw::pixel(frame, x_position - 1, y_offset + y_current, [255, 255, 255]);
+		// Separator along the bottom and right edges
+		let separator_width = separator.width * scale;
+		let (x1, y1, x2, y2) = (x1 * scale, y1 * scale, x2 * scale, y2 * scale);
+		draw::rect(target, x1, y2 - separator_width, x2, y2, separator_colour);
+		draw::rect(target, x2 - separator_width, y1, x2, y2, separator_colour);
+
+		if self.use_gradients {
+			draw::rect_gradient(
+				target,
+				x1,
+				y1,
+				x2 - separator_width,
+				y2 - separator_width,
+				&self.gradient.for_colour(background),
+				channel.background_opacity,
+			);
+		} else {
+			draw::rect_blend(
+				target,
+				x1,
+				y1,
+				x2 - separator_width,
+				y2 - separator_width,
+				background,
+				channel.background_opacity,
+			);
+		}
+
+		// Spectrograms fill the cell, so they're drawn underneath the channel name
+		if channel.display == DisplayMode::Spectrogram {
+			for (index, raw_samples) in traces.iter().enumerate() {
+				let spectrogram = &mut channel.spectrograms[index];
+				spectrogram.update(raw_samples);
+				let colour_map = spectrogram.colour_map();
+
+				draw::heatmap(
+					target,
+					x1,
+					y1 + (trace_height * index as u32) * scale,
+					(channel_width - separator.width) * scale,
+					(trace_height - separator.width) * scale,
+					spectrogram.history.make_contiguous(),
+					colour_map,
+				);
+			}
+		}
+
+		// Channel Name
+		draw::text_scaled(
+			target,
+			x1 + 4 * scale,
+			y1 + 4 * scale,
+			&channel.name,
+			self.colours.label,
+			scale,
+		);
+
+		let style = WaveformStyle {
+			thickness: channel.waveform_style.thickness * scale,
+			..channel.waveform_style
+		};
+
+		// XY plots use both traces at once, in a square in the middle of the cell
+		if let Some(phosphor) = channel.phosphor.as_mut() {
+			let size = channel_width.min(channel_height) * scale;
+			let frame_len = traces[0].len() / 2;
+			phosphor.update(&traces[0][..frame_len], &traces[1][..frame_len], size);
+
+			draw::glow(
+				target,
+				x_offset * scale + (channel_width * scale - size) / 2,
+				y_offset * scale + (channel_height * scale - size) / 2,
+				size,
+				&phosphor.intensity,
+				colour,
+			);
+		}
+
+		for (index, raw_samples) in traces.iter().enumerate() {
+			let trace_x = x_offset * scale;
+			let trace_y = (y_offset + (trace_height * index as u32)) * scale;
+
+			match channel.display {
+				DisplayMode::Waveform => draw_waveform(
+					target,
+					trace_x,
+					trace_y,
+					channel_width * scale,
+					trace_height * scale,
+					channel.visible_samples(index, raw_samples),
+					&style,
+				),
+				DisplayMode::Spectrum => {
+					let spectrum = &mut channel.spectra[index];
+					spectrum.update(raw_samples);
+
+					draw::bars(
+						target,
+						trace_x,
+						trace_y,
+						channel_width * scale,
+						trace_height * scale,
+						&spectrum.levels,
+						colour,
+					);
+					draw::bar_markers(
+						target,
+						trace_x,
+						trace_y,
+						channel_width * scale,
+						trace_height * scale,
+						&spectrum.peaks,
+						colour,
+					);
+				}
+				DisplayMode::Lane => {
+					let lane = &mut channel.lanes[index];
+					lane.update(raw_samples);
+
+					draw_lane(
+						target,
+						trace_x,
+						trace_y,
+						channel_width * scale,
+						trace_height * scale,
+						lane,
+						colour,
+					);
 				}
+				DisplayMode::Spectrogram | DisplayMode::Xy => {}
 			}
 		}
 
-		// Render frame to video
-		encoding.render_frame(frame);
+		if let Some(detector) = &channel.silence_detector {
+			draw::rect_blend(target, x1, y1, x2, y2, [0, 0, 0], detector.dim_opacity());
+		}
+	}
+}
 
-		Ok(())
+/// Draw a single trace of samples, stretched to fit the given area
+fn draw_waveform(
+	frame: &mut RgbImage,
+	x_offset: u32,
+	y_offset: u32,
+	width: u32,
+	height: u32,
+	raw_samples: &[f32],
+	style: &WaveformStyle,
+) {
+	let thickness = style.thickness.max(1);
+	let colour = style.colour.unwrap_or_default();
+
+	match style.shape {
+		WaveformShape::Line if style.antialias => draw::waveform_line_aa(
+			frame,
+			x_offset,
+			y_offset,
+			height,
+			&resample(raw_samples, width),
+			thickness,
+			colour,
+		),
+		WaveformShape::Line => draw::waveform_line(
+			frame,
+			x_offset,
+			y_offset,
+			height,
+			&resample(raw_samples, width),
+			thickness,
+			colour,
+		),
+		WaveformShape::Filled => draw::waveform_filled(
+			frame,
+			x_offset,
+			y_offset,
+			height,
+			&resample(raw_samples, width),
+			colour,
+		),
+		WaveformShape::Dots => draw::waveform_dots(
+			frame,
+			x_offset,
+			y_offset,
+			height,
+			&resample(raw_samples, width),
+			thickness,
+			colour,
+		),
+		WaveformShape::Envelope => draw::waveform_envelope(
+			frame,
+			x_offset,
+			y_offset,
+			height,
+			&column_peaks(raw_samples, width),
+			colour,
+		),
 	}
 }
+
+/// Draw the peak level of a lane, with the RMS level brighter on top, and the playhead in the
+/// middle
+fn draw_lane(
+	frame: &mut RgbImage,
+	x_offset: u32,
+	y_offset: u32,
+	width: u32,
+	height: u32,
+	lane: &Lane,
+	colour: RGB,
+) {
+	let columns = lane.columns(width);
+	let peaks: Vec<f32> = columns.iter().map(|level| level.peak).collect();
+	let rms: Vec<f32> = columns.iter().map(|level| level.rms).collect();
+
+	let dimmed = colour.map(|value| value / 2);
+	draw::waveform_envelope(frame, x_offset, y_offset, height, &peaks, dimmed);
+	draw::waveform_envelope(frame, x_offset, y_offset, height, &rms, colour);
+
+	let playhead = x_offset + width / 2;
+	draw::rect(
+		frame,
+		playhead,
+		y_offset,
+		playhead + 1,
+		y_offset + height,
+		colour,
+	);
+}
+
+/// Resample raw vector to one sample per column, by lerping between adjacent samples
+fn resample(raw_samples: &[f32], width: u32) -> Vec<f32> {
+	(0..width)
+		.into_par_iter()
+		.map(|index| {
+			let percent = (index as f32 / width as f32) * (raw_samples.len() - 1) as f32;
+			let remainder = percent % 1.0;
+			let i_low = percent.floor() as usize;
+			let i_high = percent.ceil() as usize;
+
+			// Lerp equation: (1 - t) * v0 + t * v1;
+			(1.0 - remainder) * raw_samples[i_low] + remainder * raw_samples[i_high]
+		})
+		.collect()
+}
+
+/// The loudest sample in each column, for drawing an envelope
+fn column_peaks(raw_samples: &[f32], width: u32) -> Vec<f32> {
+	(0..width as usize)
+		.into_par_iter()
+		.map(|index| {
+			let start = index * raw_samples.len() / width as usize;
+			let end = ((index + 1) * raw_samples.len() / width as usize).max(start + 1);
+
+			raw_samples[start..end.min(raw_samples.len())]
+				.iter()
+				.fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+		})
+		.collect()
+}
@@ -0,0 +1,267 @@
+use crate::{display::RGB, SCREEN_DURATION_SECS, SCREEN_FRAME_RATE};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::Deserialize;
+use std::{collections::VecDeque, f32::consts::PI, sync::Arc};
+
+/// How many samples are analysed each frame
+pub const FFT_SIZE: usize = 4096;
+
+/// The range of frequencies shown, in Hz. The top is limited to the file's Nyquist frequency.
+const MIN_FREQUENCY: f32 = 30.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+
+/// Bands quieter than this are empty, in decibels below full scale
+const MIN_DB: f32 = -60.0;
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct SpectrumSettings {
+	/// How many bars the frequency range is split into
+	pub bands: usize,
+
+	/// How slowly bars fall, from 0.0 (instantly) to 1.0 (never). Bars always rise instantly.
+	pub smoothing: f32,
+
+	/// How quickly the peak markers fall, in cell heights per second
+	pub peak_decay: f32,
+}
+
+impl Default for SpectrumSettings {
+	fn default() -> Self {
+		Self {
+			bands: 32,
+			smoothing: 0.5,
+			peak_decay: 0.5,
+		}
+	}
+}
+
+/// The colours used to draw a spectrogram, from quietest to loudest
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ColourMap {
+	/// Black, through red and yellow, to white
+	#[default]
+	Heat,
+
+	/// Dark purple, through blue and green, to yellow
+	Viridis,
+
+	/// Black to white
+	Grayscale,
+}
+
+impl ColourMap {
+	pub fn stops(&self) -> &'static [RGB] {
+		match self {
+			ColourMap::Heat => &[
+				[0, 0, 0],
+				[128, 0, 0],
+				[255, 64, 0],
+				[255, 200, 0],
+				[255, 255, 255],
+			],
+			ColourMap::Viridis => &[
+				[68, 1, 84],
+				[59, 82, 139],
+				[33, 145, 140],
+				[94, 201, 98],
+				[253, 231, 37],
+			],
+			ColourMap::Grayscale => &[[0, 0, 0], [255, 255, 255]],
+		}
+	}
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct SpectrogramSettings {
+	/// How many rows the frequency range is split into
+	pub bands: usize,
+
+	pub colour_map: ColourMap,
+
+	/// Levels at or below `min_db` use the first colour, and at or above `max_db` the last
+	pub min_db: f32,
+	pub max_db: f32,
+}
+
+impl Default for SpectrogramSettings {
+	fn default() -> Self {
+		Self {
+			bands: 128,
+			colour_map: ColourMap::default(),
+			min_db: -80.0,
+			max_db: 0.0,
+		}
+	}
+}
+
+/// Splits the next `FFT_SIZE` samples into frequency bands, spaced evenly on a log scale
+pub struct Analyser {
+	fft: Arc<dyn Fft<f32>>,
+	window: Vec<f32>,
+
+	/// The range of FFT bins that make up each band
+	band_bins: Vec<(f32, f32)>,
+}
+
+impl Analyser {
+	pub fn new(bands: usize, sample_rate: u32) -> Self {
+		let bin_hz = sample_rate as f32 / FFT_SIZE as f32;
+		let max_frequency = MAX_FREQUENCY.min(sample_rate as f32 / 2.0);
+
+		// Bands are spaced evenly on a log scale, like octaves on a piano
+		let band_bins = (0..bands)
+			.map(|band| {
+				let frequency = |band: usize| {
+					MIN_FREQUENCY * (max_frequency / MIN_FREQUENCY).powf(band as f32 / bands as f32)
+				};
+
+				(frequency(band) / bin_hz, frequency(band + 1) / bin_hz)
+			})
+			.collect();
+
+		// Hann window, so the edges of the frame don't smear across every frequency
+		let window = (0..FFT_SIZE)
+			.map(|index| 0.5 - 0.5 * (2.0 * PI * index as f32 / FFT_SIZE as f32).cos())
+			.collect();
+
+		Self {
+			fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+			window,
+			band_bins,
+		}
+	}
+
+	/// The level of each band, in decibels below full scale
+	pub fn band_decibels(&self, samples: &[f32]) -> Vec<f32> {
+		let mut buffer: Vec<Complex<f32>> = self
+			.window
+			.iter()
+			.zip(samples.iter().chain(std::iter::repeat(&0.0)))
+			.map(|(window, sample)| Complex::new(sample * window, 0.0))
+			.collect();
+
+		self.fft.process(&mut buffer);
+
+		// A full scale sine wave reaches FFT_SIZE / 4, after the window halves its level
+		let magnitudes: Vec<f32> = buffer[..FFT_SIZE / 2]
+			.iter()
+			.map(|bin| bin.norm() / (FFT_SIZE as f32 / 4.0))
+			.collect();
+
+		self.band_bins
+			.iter()
+			.map(|(low, high)| {
+				let magnitude = band_magnitude(&magnitudes, *low, *high);
+				20.0 * magnitude.max(f32::EPSILON).log10()
+			})
+			.collect()
+	}
+}
+
+/// Analyses one trace of a channel every frame, keeping track of the levels between frames
+pub struct Spectrum {
+	settings: SpectrumSettings,
+	analyser: Analyser,
+
+	/// Level of each band, from 0.0 to 1.0
+	pub levels: Vec<f32>,
+
+	/// The highest recent level of each band, falling by `peak_decay`
+	pub peaks: Vec<f32>,
+}
+
+impl Spectrum {
+	pub fn new(settings: SpectrumSettings, sample_rate: u32) -> Self {
+		let bands = settings.bands.max(1);
+
+		Self {
+			settings,
+			analyser: Analyser::new(bands, sample_rate),
+			levels: vec![0.0; bands],
+			peaks: vec![0.0; bands],
+		}
+	}
+
+	/// Analyses the next `FFT_SIZE` samples, moving the levels and peaks on by one frame
+	pub fn update(&mut self, samples: &[f32]) {
+		let peak_fall = self.settings.peak_decay / SCREEN_FRAME_RATE.as_f64() as f32;
+		let smoothing = self.settings.smoothing.clamp(0.0, 1.0);
+
+		for (band, decibels) in self.analyser.band_decibels(samples).iter().enumerate() {
+			let level = (1.0 - decibels / MIN_DB).clamp(0.0, 1.0);
+
+			let previous = self.levels[band];
+			self.levels[band] = if level > previous {
+				level
+			} else {
+				previous * smoothing + level * (1.0 - smoothing)
+			};
+
+			self.peaks[band] = (self.peaks[band] - peak_fall).max(self.levels[band]);
+		}
+	}
+}
+
+/// Keeps the frequency analysis of the last `SCREEN_DURATION_SECS` of a trace, so it can scroll
+/// across the cell like the MIDI piano roll
+pub struct Spectrogram {
+	settings: SpectrogramSettings,
+	analyser: Analyser,
+
+	/// One column per frame, oldest first. Each column holds the level of every band, from the
+	/// lowest frequency, scaled from 0.0 to 1.0 between `min_db` and `max_db`.
+	pub history: VecDeque<Vec<f32>>,
+}
+
+impl Spectrogram {
+	pub fn new(settings: SpectrogramSettings, sample_rate: u32) -> Self {
+		let bands = settings.bands.max(1);
+		let length = (*SCREEN_DURATION_SECS * SCREEN_FRAME_RATE.as_f64()).ceil() as usize;
+
+		Self {
+			settings,
+			analyser: Analyser::new(bands, sample_rate),
+			history: VecDeque::from(vec![vec![0.0; bands]; length.max(1)]),
+		}
+	}
+
+	/// Analyses the next `FFT_SIZE` samples, and scrolls the oldest column out of the history
+	pub fn update(&mut self, samples: &[f32]) {
+		let range = (self.settings.max_db - self.settings.min_db).max(f32::EPSILON);
+		let column = self
+			.analyser
+			.band_decibels(samples)
+			.iter()
+			.map(|decibels| ((decibels - self.settings.min_db) / range).clamp(0.0, 1.0))
+			.collect();
+
+		self.history.pop_front();
+		self.history.push_back(column);
+	}
+
+	pub fn colour_map(&self) -> &'static [RGB] {
+		self.settings.colour_map.stops()
+	}
+}
+
+/// The loudest bin between `low` and `high`. Bands narrower than a bin, at the bottom of the
+/// range, interpolate between the two nearest bins instead.
+fn band_magnitude(magnitudes: &[f32], low: f32, high: f32) -> f32 {
+	let first = low.ceil() as usize;
+	let last = (high.floor() as usize).min(magnitudes.len() - 1);
+
+	if first <= last {
+		return magnitudes[first..=last]
+			.iter()
+			.fold(0.0f32, |max, &magnitude| max.max(magnitude));
+	}
+
+	let centre = ((low + high) / 2.0).min((magnitudes.len() - 2) as f32);
+	let index = centre.floor() as usize;
+	let t = centre.fract();
+
+	(1.0 - t) * magnitudes[index] + t * magnitudes[index + 1]
+}
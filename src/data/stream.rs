@@ -0,0 +1,140 @@
+use super::channel::{stereo_pair_ref, StereoMode};
+use std::{
+	sync::mpsc::{sync_channel, Receiver, SyncSender},
+	thread,
+};
+use symphonia::core::{codecs::Decoder, errors::Error, formats::FormatReader};
+
+/// How many blocks each second of audio is split into before being sent to the render thread
+const BLOCKS_PER_SEC: usize = 10;
+
+/// How many blocks the decoder can get ahead of the playhead before it waits for the render
+/// thread to catch up, ie. two seconds of audio
+const BLOCKS_AHEAD: usize = 20;
+
+/// Decoded and mixed samples for every trace, or the error that stopped decoding
+pub type Block = Result<Vec<Vec<f32>>, Error>;
+
+/// Everything a worker thread needs to decode a channel, taken from the channel once it has been
+/// loaded and seeked to the start of the song
+pub struct DecodeJob {
+	pub file: String,
+	pub format: Box<dyn FormatReader>,
+	pub decoder: Box<dyn Decoder>,
+	pub track_id: u32,
+	pub sample_rate: u32,
+	pub stereo_mode: StereoMode,
+	pub level_scale: f32,
+	pub skip_samples: usize,
+}
+
+/// Decodes a channel on its own thread, keeping a bounded buffer of blocks ahead of the playhead
+pub struct DecodeStream {
+	receiver: Receiver<Block>,
+}
+
+impl DecodeStream {
+	pub fn spawn(job: DecodeJob) -> Self {
+		let (sender, receiver) = sync_channel(BLOCKS_AHEAD);
+
+		thread::Builder::new()
+			.name(format!("decode {}", job.file))
+			.spawn(move || job.run(sender))
+			.expect("Could not start decoder thread");
+
+		Self { receiver }
+	}
+
+	/// Waits for the next block of samples. Returns `None` once the whole file has been decoded.
+	pub fn next_block(&self) -> Option<Block> {
+		self.receiver.recv().ok()
+	}
+}
+
+impl DecodeJob {
+	fn run(mut self, sender: SyncSender<Block>) {
+		let block_samples = (self.sample_rate as usize / BLOCKS_PER_SEC).max(1);
+		let mut pending = vec![Vec::new(); self.stereo_mode.trace_count()];
+
+		loop {
+			// Get the next packet from the media format.
+			let packet = match self.format.next_packet() {
+				Ok(packet) => packet,
+				Err(Error::IoError(_err)) => {
+					// The end of the file, nothing more to decode
+					break;
+				}
+				Err(err) => {
+					// A unrecoverable error occured, halt decoding.
+					let _ = sender.send(Err(err));
+					return;
+				}
+			};
+
+			// Consume any new metadata that has been read since the last packet.
+			while !self.format.metadata().is_latest() {
+				// Pop the old head of the metadata queue.
+				self.format.metadata().pop();
+
+				// Consume the new metadata at the head of the metadata queue.
+			}
+
+			if packet.track_id() != self.track_id {
+				println!(
+					"Error rendering \"{}\": Track doesn't match, skipping...",
+					&self.file
+				);
+				continue;
+			}
+
+			// Decode the packet into audio samples.
+			let (mut left, mut right) = match self.decoder.decode(&packet) {
+				Ok(decoded) => stereo_pair_ref(decoded),
+				Err(Error::IoError(_)) => {
+					// The packet failed to decode due to an IO error, skip the packet.
+					continue;
+				}
+				Err(Error::DecodeError(_)) => {
+					// The packet failed to decode due to invalid data, skip the packet.
+					continue;
+				}
+				Err(err) => {
+					// An unrecoverable error occured, halt decoding.
+					let _ = sender.send(Err(err));
+					return;
+				}
+			};
+
+			if self.skip_samples > 0 {
+				let skip = self.skip_samples.min(left.len());
+				left.drain(0..skip);
+				right.drain(0..skip);
+				self.skip_samples -= skip;
+			}
+
+			for (buffer, trace) in pending.iter_mut().zip(self.stereo_mode.mix(left, right)) {
+				buffer.extend(trace.iter().map(|sample| sample * self.level_scale));
+			}
+
+			// Blocks until the render thread has room for more. If the song has stopped rendering,
+			// the receiver has gone and there's nothing left to do.
+			while pending[0].len() >= block_samples {
+				let rest = pending
+					.iter_mut()
+					.map(|buffer| buffer.split_off(block_samples))
+					.collect();
+
+				if sender
+					.send(Ok(std::mem::replace(&mut pending, rest)))
+					.is_err()
+				{
+					return;
+				}
+			}
+		}
+
+		if !pending[0].is_empty() {
+			let _ = sender.send(Ok(pending));
+		}
+	}
+}
@@ -0,0 +1,135 @@
+use crate::display::RGB;
+use serde::Deserialize;
+
+/// What is drawn in a channel's cell
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayMode {
+	/// An oscilloscope view of the audio
+	#[default]
+	Waveform,
+
+	/// Bars showing the level of each frequency band
+	Spectrum,
+
+	/// A heatmap of the levels of each frequency band, scrolling from right to left
+	Spectrogram,
+
+	/// The left channel plotted against the right channel, making Lissajous figures
+	Xy,
+
+	/// The peak and RMS level of the surrounding seconds, scrolling past a playhead in the middle
+	Lane,
+}
+
+/// How each trace of a channel is drawn
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WaveformShape {
+	/// A connected line through every sample
+	#[default]
+	Line,
+
+	/// The area between the waveform and the centre line
+	Filled,
+
+	/// Unconnected dots, one for every `thickness` pixels
+	Dots,
+
+	/// The peak level of the samples in each column, mirrored around the centre line
+	Envelope,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct WaveformStyle {
+	pub shape: WaveformShape,
+
+	/// Width of the line, or size of each dot, in pixels before scaling
+	pub thickness: u32,
+
+	/// Defaults to the theme's foreground colour
+	pub colour: Option<RGB>,
+
+	/// Smooth the edges of `line` waveforms
+	pub antialias: bool,
+}
+
+impl Default for WaveformStyle {
+	fn default() -> Self {
+		Self {
+			shape: WaveformShape::default(),
+			thickness: 1,
+			colour: None,
+			antialias: false,
+		}
+	}
+}
+
+/// Which way a channel's background gradient runs, from the first colour stop to the last
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GradientDirection {
+	/// From the top of the cell to the bottom
+	#[default]
+	Vertical,
+
+	/// From the left of the cell to the right
+	Horizontal,
+
+	/// From the centre of the cell out to the corners
+	Radial,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct GradientSettings {
+	pub direction: GradientDirection,
+
+	/// Colours spread evenly across the cell. Defaults to fading from the channel's colour to black.
+	pub stops: Option<Vec<RGB>>,
+
+	/// How strongly the stops are mixed over the channel's colour, from 0.0 to 1.0
+	pub intensity: f32,
+}
+
+impl Default for GradientSettings {
+	fn default() -> Self {
+		Self {
+			direction: GradientDirection::default(),
+			stops: None,
+			intensity: 0.5,
+		}
+	}
+}
+
+/// A channel's background gradient, ready to be drawn
+pub struct Gradient {
+	pub stops: Vec<RGB>,
+	pub direction: GradientDirection,
+}
+
+impl GradientSettings {
+	/// The gradient for a channel's background, with the stops mixed over the channel's colour
+	pub fn for_colour(&self, colour: RGB) -> Gradient {
+		let intensity = self.intensity.clamp(0.0, 1.0);
+		let stops = match &self.stops {
+			Some(stops) if !stops.is_empty() => stops.clone(),
+			_ => vec![colour, [0, 0, 0]],
+		};
+
+		Gradient {
+			stops: stops
+				.iter()
+				.map(|stop| {
+					[0, 1, 2].map(|channel| {
+						(colour[channel] as f32 * (1.0 - intensity)
+							+ stop[channel] as f32 * intensity)
+							.round() as u8
+					})
+				})
+				.collect(),
+			direction: self.direction,
+		}
+	}
+}
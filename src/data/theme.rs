@@ -0,0 +1,105 @@
+use super::error::LoadError;
+use crate::display::RGB;
+use serde::Deserialize;
+use std::{fs::File, io::BufReader};
+
+/// The colours used for everything that isn't chosen per channel
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct Theme {
+	/// Behind the cells, and the lyrics
+	pub background: RGB,
+
+	/// Waveforms, spectra, and MIDI notes
+	pub foreground: RGB,
+
+	/// Channel names
+	pub label: RGB,
+
+	/// Drawn behind MIDI notes and channel names, so they stand out
+	pub shadow: RGB,
+
+	/// The line between cells
+	pub separator: RGB,
+
+	pub lyric: RGB,
+
+	/// The background of song channels that don't choose a colour
+	pub channel: RGB,
+
+	/// The background of MIDI channels that don't choose a colour
+	pub midi_channel: RGB,
+}
+
+impl Default for Theme {
+	fn default() -> Self {
+		DARK
+	}
+}
+
+const DARK: Theme = Theme {
+	background: [0, 0, 0],
+	foreground: [255, 255, 255],
+	label: [255, 255, 255],
+	shadow: [0, 0, 0],
+	separator: [0, 0, 0],
+	lyric: [255, 255, 255],
+	channel: [0, 0, 0],
+	midi_channel: [24, 24, 24],
+};
+
+const LIGHT: Theme = Theme {
+	background: [240, 240, 240],
+	foreground: [16, 16, 16],
+	label: [16, 16, 16],
+	shadow: [255, 255, 255],
+	separator: [240, 240, 240],
+	lyric: [16, 16, 16],
+	channel: [208, 208, 208],
+	midi_channel: [208, 208, 208],
+};
+
+const NEON: Theme = Theme {
+	background: [8, 0, 16],
+	foreground: [0, 255, 204],
+	label: [255, 64, 224],
+	shadow: [0, 0, 0],
+	separator: [48, 0, 96],
+	lyric: [255, 240, 0],
+	channel: [24, 0, 48],
+	midi_channel: [24, 0, 48],
+};
+
+/// Either the name of a built-in theme, a path to a theme JSON file, or the theme itself
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum ThemeConfig {
+	Named(String),
+	Inline(Theme),
+}
+
+impl Default for ThemeConfig {
+	fn default() -> Self {
+		ThemeConfig::Inline(Theme::default())
+	}
+}
+
+impl ThemeConfig {
+	pub fn load(&self) -> Result<Theme, LoadError> {
+		let path = match self {
+			ThemeConfig::Inline(theme) => return Ok(*theme),
+			ThemeConfig::Named(name) => match name.as_ref() {
+				"dark" => return Ok(DARK),
+				"light" => return Ok(LIGHT),
+				"neon" => return Ok(NEON),
+				path => path,
+			},
+		};
+
+		let file = File::open(path).map_err(|err| LoadError::io(path, err))?;
+
+		let rdr = BufReader::new(file);
+
+		serde_json::from_reader(rdr).map_err(|err| LoadError::config(path, err))
+	}
+}
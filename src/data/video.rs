@@ -1,3 +1,4 @@
+use super::{error::EncodeError, mux::mux_master_audio};
 use crate::{SCREEN_FRAME_RATE, SCREEN_HEIGHT, SCREEN_SCALE, SCREEN_WIDTH};
 use fast_image_resize::{
 	images::{Image, ImageRef},
@@ -14,13 +15,26 @@ pub struct Encoding {
 	pub frame_duration: Time,
 	pub resizer: Resizer,
 	pub resize_options: ResizeOptions,
+
+	/// Final output path. When master audio is provided, the video is first written to
+	/// `video_file_render` and muxed into this file with the audio on `flush`.
+	pub video_file_out: String,
+	pub video_file_render: String,
+	pub master_audio: Option<String>,
+
+	/// Where the render starts in the song, so the master audio can be trimmed to match
+	pub start_secs: f64,
 }
 
 impl Encoding {
-	pub fn new(video_file_out: &str) -> Self {
+	pub fn new(video_file_out: &str, master_audio: &Option<String>, start_secs: f64) -> Self {
 		let width = *SCREEN_WIDTH * *SCREEN_SCALE;
 		let height = *SCREEN_HEIGHT * *SCREEN_SCALE;
-		let destination: Location = PathBuf::from(video_file_out).into();
+		let video_file_render = match master_audio {
+			Some(_) => PathBuf::from(video_file_out).with_extension("video.mp4"),
+			None => PathBuf::from(video_file_out),
+		};
+		let destination: Location = video_file_render.clone().into();
 		let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false);
 		let encoder = Encoder::new(&destination, settings).expect("Failed to create encoder");
 		let resize_options = ResizeOptions::new().resize_alg(ResizeAlg::Nearest);
@@ -28,9 +42,16 @@ impl Encoding {
 		Encoding {
 			encoder,
 			position: Time::zero(),
-			frame_duration: Time::from_nth_of_a_second(*SCREEN_FRAME_RATE),
+			frame_duration: Time::new(
+				Some(SCREEN_FRAME_RATE.denominator as i64),
+				(1, SCREEN_FRAME_RATE.numerator as i32).into(),
+			),
 			resizer: Resizer::new(),
 			resize_options,
+			video_file_out: video_file_out.to_owned(),
+			video_file_render: video_file_render.to_string_lossy().into_owned(),
+			master_audio: master_audio.clone(),
+			start_secs,
 		}
 	}
 
@@ -57,6 +78,15 @@ impl Encoding {
 
 	pub fn render_frame(&mut self, buffer: &mut RgbImage) {
 		let pixels = self.resize_frame(buffer);
+		self.encode_pixels(pixels);
+	}
+
+	/// Render a frame that has already been drawn at the scaled output resolution
+	pub fn render_scaled_frame(&mut self, buffer: &RgbImage) {
+		self.encode_pixels(buffer.as_raw().to_vec());
+	}
+
+	fn encode_pixels(&mut self, pixels: Vec<u8>) {
 		let frame: Array3<u8> = ndarray::Array3::from_shape_vec(
 			(
 				(*SCREEN_HEIGHT * *SCREEN_SCALE) as usize,
@@ -75,7 +105,35 @@ impl Encoding {
 		self.position = self.position.aligned_with(self.frame_duration).add();
 	}
 
-	pub fn flush(&mut self) {
-		self.encoder.finish().unwrap();
+	pub fn flush(mut self) -> Result<(), EncodeError> {
+		self.encoder.finish().map_err(|err| EncodeError::Finish {
+			path: self.video_file_render.clone(),
+			reason: err.to_string(),
+		})?;
+
+		let Some(master_audio) = &self.master_audio else {
+			return Ok(());
+		};
+
+		// Close the video-only render before reading it back
+		drop(self.encoder);
+
+		println!("\nMuxing master audio \"{}\"", master_audio);
+		mux_master_audio(
+			&self.video_file_render,
+			master_audio,
+			&self.video_file_out,
+			self.start_secs,
+			self.position,
+		)
+		.map_err(|err| EncodeError::Mux {
+			path: self.video_file_render.clone(),
+			reason: err.to_string(),
+		})?;
+
+		std::fs::remove_file(&self.video_file_render).map_err(|error| EncodeError::Cleanup {
+			path: self.video_file_render.clone(),
+			error,
+		})
 	}
 }
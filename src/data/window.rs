@@ -1,4 +1,4 @@
-use super::defaults::default_five;
+use super::{defaults::default_five, error::LoadError};
 use crate::Args;
 use serde::Deserialize;
 use std::{fs::File, io::BufReader};
@@ -8,58 +8,111 @@ pub struct Window {
 	pub width: u32,
 	pub height: u32,
 	pub scale: u32,
-	pub frame_rate: usize,
+	pub frame_rate: FrameRate,
 
 	#[serde(default = "default_five")]
 	pub duration_secs: f64,
+
+	/// Draw waveforms at the scaled output resolution, rather than scaling them up afterwards
+	#[serde(default)]
+	pub supersample: bool,
+}
+
+/// Frames per second as a fraction, so broadcast rates like 29.97 (30000/1001) are exact
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(try_from = "FrameRateConfig")]
+pub struct FrameRate {
+	pub numerator: usize,
+	pub denominator: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FrameRateConfig {
+	Number(usize),
+	Text(String),
+}
+
+impl TryFrom<FrameRateConfig> for FrameRate {
+	type Error = String;
+
+	fn try_from(value: FrameRateConfig) -> Result<Self, Self::Error> {
+		let frame_rate = match value {
+			FrameRateConfig::Number(frames) => Some(FrameRate::new(frames, 1)),
+			FrameRateConfig::Text(text) => text.split_once('/').and_then(|(num, den)| {
+				Some(FrameRate::new(
+					num.trim().parse().ok()?,
+					den.trim().parse().ok()?,
+				))
+			}),
+		};
+
+		frame_rate
+			.filter(|rate| rate.numerator > 0 && rate.denominator > 0)
+			.ok_or_else(|| "Expected a frame rate like 30 or \"30000/1001\"".to_owned())
+	}
+}
+
+impl FrameRate {
+	pub const fn new(numerator: usize, denominator: usize) -> Self {
+		FrameRate {
+			numerator,
+			denominator,
+		}
+	}
+
+	/// Frames per second, for anything that doesn't need to count samples exactly
+	pub fn as_f64(self) -> f64 {
+		self.numerator as f64 / self.denominator as f64
+	}
 }
 
 const DEFAULT_169: Window = Window {
 	width: 480,
 	height: 270,
 	scale: 4,
-	frame_rate: 60,
+	frame_rate: FrameRate::new(60, 1),
 	duration_secs: 5.0,
+	supersample: false,
 };
 
 const DEFAULT_916: Window = Window {
 	width: 216,
 	height: 384,
 	scale: 5,
-	frame_rate: 30,
+	frame_rate: FrameRate::new(30, 1),
 	duration_secs: 3.0,
+	supersample: false,
 };
 
 const DEFAULT_918: Window = Window {
 	width: 216,
 	height: 432,
 	scale: 5,
-	frame_rate: 30,
+	frame_rate: FrameRate::new(30, 1),
 	duration_secs: 3.0,
+	supersample: false,
 };
 
 impl Window {
-	pub fn load_from_args(cmd: &Args) -> Self {
+	pub fn load_from_args(cmd: &Args) -> Result<Self, LoadError> {
 		if let Some(preset) = &cmd.window_preset {
-			match preset.as_ref() {
-				"16x9" => return DEFAULT_169,
-				"9x16" => return DEFAULT_916,
-				"9x18" => return DEFAULT_918,
-				_ => panic!("This preset does not exist - use '16x9', '9x16', or '9x18'."),
-			}
+			return match preset.as_ref() {
+				"16x9" => Ok(DEFAULT_169),
+				"9x16" => Ok(DEFAULT_916),
+				"9x18" => Ok(DEFAULT_918),
+				_ => Err(LoadError::InvalidPreset(preset.clone())),
+			};
 		}
 
 		Window::load_from_file(&cmd.window)
 	}
 
-	pub fn load_from_file(window_path: &str) -> Self {
-		let file = File::open(window_path);
-		if file.is_err() {
-			panic!("Could not open window.json");
-		}
+	pub fn load_from_file(window_path: &str) -> Result<Self, LoadError> {
+		let file = File::open(window_path).map_err(|err| LoadError::io(window_path, err))?;
 
-		let rdr = BufReader::new(file.unwrap());
+		let rdr = BufReader::new(file);
 
-		serde_json::from_reader(rdr).unwrap()
+		serde_json::from_reader(rdr).map_err(|err| LoadError::config(window_path, err))
 	}
 }
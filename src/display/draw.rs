@@ -1,4 +1,5 @@
 use super::{font::*, RGB};
+use crate::data::style::{Gradient, GradientDirection};
 use image::RgbImage;
 
 /// Draw a single pixel, with a given colour, to the screen at a given point
@@ -9,8 +10,75 @@ pub fn pixel(frame: &mut RgbImage, x: u32, y: u32, colour: RGB) {
 	p.0[2] = colour[2];
 }
 
-/// Draw a single letter to the screen based on the blit32 font
-fn letter(frame: &mut RgbImage, x: u32, y: u32, letter: u32, colour: RGB) {
+/// Mix a colour into the pixel already on screen, where an `alpha` of 1.0 is fully opaque.
+/// Points outside of the frame are ignored.
+pub fn pixel_blend(frame: &mut RgbImage, x: u32, y: u32, colour: RGB, alpha: f32) {
+	if alpha <= 0.0 || x >= frame.width() || y >= frame.height() {
+		return;
+	}
+
+	let alpha = alpha.min(1.0);
+	let p = frame.get_pixel_mut(x, y);
+	for (channel, value) in p.0.iter_mut().zip(colour) {
+		*channel = (*channel as f32 * (1.0 - alpha) + value as f32 * alpha).round() as u8;
+	}
+}
+
+/// Draw an anti-aliased line between two points, using Xiaolin Wu's algorithm
+pub fn line_aa(frame: &mut RgbImage, x1: f32, y1: f32, x2: f32, y2: f32, colour: RGB) {
+	let steep = (y2 - y1).abs() > (x2 - x1).abs();
+
+	// Always step along the longest axis, from left to right
+	let (x1, y1, x2, y2) = if steep {
+		(y1, x1, y2, x2)
+	} else {
+		(x1, y1, x2, y2)
+	};
+	let (x1, y1, x2, y2) = if x1 > x2 {
+		(x2, y2, x1, y1)
+	} else {
+		(x1, y1, x2, y2)
+	};
+
+	let dx = x2 - x1;
+	let gradient = if dx == 0.0 { 1.0 } else { (y2 - y1) / dx };
+
+	let mut plot = |x: f32, y: f32, alpha: f32| {
+		if x < 0.0 || y < 0.0 {
+			return;
+		}
+
+		if steep {
+			pixel_blend(frame, y as u32, x as u32, colour, alpha);
+		} else {
+			pixel_blend(frame, x as u32, y as u32, colour, alpha);
+		}
+	};
+
+	// Each end point is split between the two pixels it falls between
+	let x_start = x1.round();
+	let y_start = y1 + gradient * (x_start - x1);
+	let x_gap = 1.0 - (x1 + 0.5).fract();
+	plot(x_start, y_start.floor(), (1.0 - y_start.fract()) * x_gap);
+	plot(x_start, y_start.floor() + 1.0, y_start.fract() * x_gap);
+
+	let x_end = x2.round();
+	let y_end = y2 + gradient * (x_end - x2);
+	let x_gap = (x2 + 0.5).fract();
+	plot(x_end, y_end.floor(), (1.0 - y_end.fract()) * x_gap);
+	plot(x_end, y_end.floor() + 1.0, y_end.fract() * x_gap);
+
+	let mut y = y_start + gradient;
+	for x in (x_start as i32 + 1)..(x_end as i32) {
+		plot(x as f32, y.floor(), 1.0 - y.fract());
+		plot(x as f32, y.floor() + 1.0, y.fract());
+		y += gradient;
+	}
+}
+
+/// Draw a single letter to the screen based on the blit32 font, with each pixel of the font
+/// drawn as a `scale` by `scale` square
+fn letter(frame: &mut RgbImage, x: u32, y: u32, letter: u32, colour: RGB, scale: u32) {
 	for line_offset in 0..FONT_HEIGHT {
 		for letter_offset in 0..FONT_WIDTH {
 			let shift = (line_offset * FONT_WIDTH) + letter_offset;
@@ -18,7 +86,16 @@ fn letter(frame: &mut RgbImage, x: u32, y: u32, letter: u32, colour: RGB) {
 			// (essentially a boolean at this point)
 			let chr = (letter >> shift) & 0b00000001;
 			if chr == 1 {
-				pixel(frame, x + letter_offset, y + line_offset, colour);
+				for (sx, sy) in (0..scale).flat_map(|sx| (0..scale).map(move |sy| (sx, sy))) {
+					// Names can run off the edge of cells that are collapsing
+					pixel_blend(
+						frame,
+						x + (letter_offset * scale) + sx,
+						y + (line_offset * scale) + sy,
+						colour,
+						1.0,
+					);
+				}
 			}
 		}
 	}
@@ -26,11 +103,13 @@ fn letter(frame: &mut RgbImage, x: u32, y: u32, letter: u32, colour: RGB) {
 
 /// Draw a string of text to the screen.
 /// This will ignore any characters outside of the range of valid characters.
-pub fn text(frame: &mut RgbImage, x: u32, y: u32, text: &str) {
-	text_colour(frame, x, y, text, [0xff, 0xff, 0xff]);
+pub fn text_colour(frame: &mut RgbImage, x: u32, y: u32, text: &str, colour: RGB) {
+	text_scaled(frame, x, y, text, colour, 1);
 }
 
-pub fn text_colour(frame: &mut RgbImage, x: u32, y: u32, text: &str, colour: RGB) {
+/// Draw a string of text to a frame that's `scale` times the base resolution, so it keeps the
+/// same pixelated look
+pub fn text_scaled(frame: &mut RgbImage, x: u32, y: u32, text: &str, colour: RGB, scale: u32) {
 	text.chars()
 		.filter_map(|letter| {
 			let code = letter as usize;
@@ -45,7 +124,8 @@ pub fn text_colour(frame: &mut RgbImage, x: u32, y: u32, text: &str, colour: RGB
 		})
 		.enumerate()
 		.for_each(|(tx, index)| {
-			letter(frame, (tx as u32 * FONT_SEPARATION) + x, y, index, colour);
+			let x = (tx as u32 * FONT_SEPARATION * scale) + x;
+			letter(frame, x, y, index, colour, scale);
 		});
 }
 
@@ -57,18 +137,354 @@ pub fn rect(frame: &mut RgbImage, x1: u32, y1: u32, x2: u32, y2: u32, colour: RG
 	}
 }
 
-pub fn rect_gradient(frame: &mut RgbImage, x1: u32, y1: u32, x2: u32, y2: u32, colour: RGB) {
-	let mut col_prev = colour;
+/// Mix a colour into every pixel of a rectangle, where an `alpha` of 1.0 is fully opaque
+pub fn rect_blend(
+	frame: &mut RgbImage,
+	x1: u32,
+	y1: u32,
+	x2: u32,
+	y2: u32,
+	colour: RGB,
+	alpha: f32,
+) {
+	for y in y1..y2 {
+		for x in x1..x2 {
+			pixel_blend(frame, x, y, colour, alpha);
+		}
+	}
+}
+
+/// Fill a rectangle with a gradient, where an `alpha` of 1.0 is fully opaque
+pub fn rect_gradient(
+	frame: &mut RgbImage,
+	x1: u32,
+	y1: u32,
+	x2: u32,
+	y2: u32,
+	gradient: &Gradient,
+	alpha: f32,
+) {
+	let width = x2.saturating_sub(x1).max(2) as f32;
+	let height = y2.saturating_sub(y1).max(2) as f32;
+
+	// Radial gradients reach the last stop in the corners
+	let centre_x = (width - 1.0) / 2.0;
+	let centre_y = (height - 1.0) / 2.0;
+	let radius = (centre_x * centre_x + centre_y * centre_y).sqrt();
 
 	for y in y1..y2 {
 		for x in x1..x2 {
-			pixel(frame, x, y, col_prev);
+			let (dx, dy) = ((x - x1) as f32, (y - y1) as f32);
+
+			let position = match gradient.direction {
+				GradientDirection::Vertical => dy / (height - 1.0),
+				GradientDirection::Horizontal => dx / (width - 1.0),
+				GradientDirection::Radial => {
+					((dx - centre_x).powi(2) + (dy - centre_y).powi(2)).sqrt() / radius
+				}
+			};
+
+			pixel_blend(frame, x, y, colour_map(&gradient.stops, position), alpha);
+		}
+	}
+}
+
+/// Map a sample from -1.0..1.0 onto a row of an area `height` pixels tall, clipping anything
+/// louder
+pub fn sample_to_y(sample: f32, height: u32) -> u32 {
+	((sample + 1.0) * 0.5 * height as f32).clamp(0.0, (height - 1) as f32) as u32
+}
+
+/// Same as `sample_to_y`, but keeps the fraction of a pixel for anti-aliasing
+pub fn sample_to_y_precise(sample: f32, height: u32) -> f32 {
+	((sample + 1.0) * 0.5 * height as f32).clamp(0.0, (height - 1) as f32)
+}
+
+/// Draw a connected line through one sample per column, `thickness` pixels wide
+pub fn waveform_line(
+	frame: &mut RgbImage,
+	x_offset: u32,
+	y_offset: u32,
+	height: u32,
+	samples: &[f32],
+	thickness: u32,
+	colour: RGB,
+) {
+	let x_max = x_offset + samples.len() as u32;
+	let y_max = y_offset + height;
+
+	for (x, pair) in samples.windows(2).enumerate() {
+		let x_position = x_offset + x as u32;
+
+		let mut y_previous = sample_to_y(pair[0], height);
+		let mut y_current = sample_to_y(pair[1], height);
+
+		// Swap samples so it's always drawing downwards
+		if y_previous > y_current {
+			(y_current, y_previous) = (y_previous, y_current);
+		}
+
+		// Connect a line to the previous sample, centred on the trace
+		rect(
+			frame,
+			x_position,
+			(y_offset + y_previous)
+				.saturating_sub((thickness - 1) / 2)
+				.max(y_offset),
+			(x_position + thickness).min(x_max),
+			(y_offset + y_current + thickness / 2 + 1).min(y_max),
+			colour,
+		);
+	}
+}
+
+/// Same as `waveform_line`, but with smooth anti-aliased edges
+pub fn waveform_line_aa(
+	frame: &mut RgbImage,
+	x_offset: u32,
+	y_offset: u32,
+	height: u32,
+	samples: &[f32],
+	thickness: u32,
+	colour: RGB,
+) {
+	let x_offset = x_offset as f32;
+	let y_offset = y_offset as f32;
+
+	// Thicker lines are drawn as several lines stacked on top of each other
+	let spread = (thickness - 1) as f32 / 2.0;
+	let y_min = y_offset + spread;
+	let y_max = y_offset + (height - 1) as f32 - spread;
+
+	for (x, pair) in samples.windows(2).enumerate() {
+		let y1 = (y_offset + sample_to_y_precise(pair[0], height)).clamp(y_min, y_max);
+		let y2 = (y_offset + sample_to_y_precise(pair[1], height)).clamp(y_min, y_max);
+		let x1 = x_offset + x as f32;
+
+		for line in 0..thickness {
+			let shift = line as f32 - spread;
+			line_aa(frame, x1, y1 + shift, x1 + 1.0, y2 + shift, colour);
 		}
+	}
+}
+
+/// Fill the area between each sample and the centre line
+pub fn waveform_filled(
+	frame: &mut RgbImage,
+	x_offset: u32,
+	y_offset: u32,
+	height: u32,
+	samples: &[f32],
+	colour: RGB,
+) {
+	let centre = sample_to_y(0.0, height);
+
+	for (x, sample) in samples.iter().enumerate() {
+		let y = sample_to_y(*sample, height);
+		let x_position = x_offset + x as u32;
+
+		rect(
+			frame,
+			x_position,
+			y_offset + y.min(centre),
+			x_position + 1,
+			y_offset + y.max(centre) + 1,
+			colour,
+		);
+	}
+}
+
+/// Draw each sample as an unconnected square dot, `size` pixels wide
+pub fn waveform_dots(
+	frame: &mut RgbImage,
+	x_offset: u32,
+	y_offset: u32,
+	height: u32,
+	samples: &[f32],
+	size: u32,
+	colour: RGB,
+) {
+	let x_max = x_offset + samples.len() as u32;
+	let y_max = y_offset + height;
+
+	for (x, sample) in samples.iter().enumerate().step_by(size as usize) {
+		let x_position = x_offset + x as u32;
+		let y_position = (y_offset + sample_to_y(*sample, height)).saturating_sub((size - 1) / 2);
+
+		rect(
+			frame,
+			x_position,
+			y_position.max(y_offset),
+			(x_position + size).min(x_max),
+			(y_position + size).min(y_max),
+			colour,
+		);
+	}
+}
+
+/// Copy an area of `source` onto `destination`, which is `scale` times larger, so more detail can
+/// be drawn on top at the output resolution
+pub fn upscale(
+	source: &RgbImage,
+	destination: &mut RgbImage,
+	x1: u32,
+	y1: u32,
+	x2: u32,
+	y2: u32,
+	scale: u32,
+) {
+	for y in y1 * scale..y2 * scale {
+		for x in x1 * scale..x2 * scale {
+			let colour = source.get_pixel(x / scale, y / scale).0;
+			pixel(destination, x, y, colour);
+		}
+	}
+}
+
+/// Draw the level of each column mirrored above and below the centre line. `peaks` should be
+/// positive, from 0.0 to 1.0.
+pub fn waveform_envelope(
+	frame: &mut RgbImage,
+	x_offset: u32,
+	y_offset: u32,
+	height: u32,
+	peaks: &[f32],
+	colour: RGB,
+) {
+	for (x, peak) in peaks.iter().enumerate() {
+		let x_position = x_offset + x as u32;
+
+		rect(
+			frame,
+			x_position,
+			y_offset + sample_to_y(-peak, height),
+			x_position + 1,
+			y_offset + sample_to_y(*peak, height) + 1,
+			colour,
+		);
+	}
+}
+
+/// Draw one bar per level, from the bottom of the area. Levels range from 0.0 to 1.0.
+pub fn bars(
+	frame: &mut RgbImage,
+	x_offset: u32,
+	y_offset: u32,
+	width: u32,
+	height: u32,
+	levels: &[f32],
+	colour: RGB,
+) {
+	for (index, x1, x2) in bar_columns(x_offset, width, levels.len()) {
+		let bar_height = (levels[index].clamp(0.0, 1.0) * height as f32).round() as u32;
+		rect(
+			frame,
+			x1,
+			y_offset + height - bar_height,
+			x2,
+			y_offset + height,
+			colour,
+		);
+	}
+}
+
+/// Draw a thin marker at each level above 0.0, in the same columns as `bars`
+pub fn bar_markers(
+	frame: &mut RgbImage,
+	x_offset: u32,
+	y_offset: u32,
+	width: u32,
+	height: u32,
+	levels: &[f32],
+	colour: RGB,
+) {
+	let thickness = (height / 64).max(1);
+
+	for (index, x1, x2) in bar_columns(x_offset, width, levels.len()) {
+		if levels[index] <= 0.0 {
+			continue;
+		}
+
+		let y = y_offset + height - (levels[index].clamp(0.0, 1.0) * height as f32).round() as u32;
+		rect(
+			frame,
+			x1,
+			y.saturating_sub(thickness).max(y_offset),
+			x2,
+			y,
+			colour,
+		);
+	}
+}
+
+/// Splits an area into evenly spaced bars, leaving a gap between them when there's room
+fn bar_columns(x_offset: u32, width: u32, count: usize) -> impl Iterator<Item = (usize, u32, u32)> {
+	let bar_width = width as f32 / count as f32;
+	let gap = (bar_width >= 3.0) as u32;
+
+	(0..count).map(move |index| {
+		let x1 = x_offset + (index as f32 * bar_width).round() as u32;
+		let x2 = x_offset + ((index + 1) as f32 * bar_width).round() as u32;
+
+		(
+			index,
+			x1,
+			x2.saturating_sub(gap).max(x1 + 1).min(x_offset + width),
+		)
+	})
+}
+
+/// Pick a colour between evenly spaced `stops`, where `value` ranges from 0.0 to 1.0
+pub fn colour_map(stops: &[RGB], value: f32) -> RGB {
+	let position = value.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+	let index = (position.floor() as usize).min(stops.len().saturating_sub(2));
+	let t = position - index as f32;
+
+	let low = stops[index];
+	let high = stops[(index + 1).min(stops.len() - 1)];
+
+	[0, 1, 2].map(|channel| (low[channel] as f32 * (1.0 - t) + high[channel] as f32 * t) as u8)
+}
+
+/// Draw columns of values, from 0.0 to 1.0, stretched to fill the area. The first value in each
+/// column is drawn at the bottom.
+pub fn heatmap(
+	frame: &mut RgbImage,
+	x_offset: u32,
+	y_offset: u32,
+	width: u32,
+	height: u32,
+	columns: &[Vec<f32>],
+	stops: &[RGB],
+) {
+	for x in 0..width {
+		let column = &columns[x as usize * columns.len() / width as usize];
+
+		for y in 0..height {
+			let row = (height - 1 - y) as usize * column.len() / height as usize;
+			pixel(
+				frame,
+				x_offset + x,
+				y_offset + y,
+				colour_map(stops, column[row]),
+			);
+		}
+	}
+}
+
+/// Blend a colour over a square area, using a brightness from 0.0 to 1.0 for each pixel, in rows
+pub fn glow(
+	frame: &mut RgbImage,
+	x_offset: u32,
+	y_offset: u32,
+	size: u32,
+	intensity: &[f32],
+	colour: RGB,
+) {
+	for (index, alpha) in intensity.iter().enumerate() {
+		let x = index as u32 % size;
+		let y = index as u32 / size;
 
-		col_prev = [
-			col_prev[0] - (y % 3 == 0 && col_prev[0] > 0) as u8,
-			col_prev[1] - (y % 3 == 1 && col_prev[1] > 0) as u8,
-			col_prev[2] - (y % 3 == 2 && col_prev[2] > 0) as u8,
-		];
+		pixel_blend(frame, x_offset + x, y_offset + y, colour, *alpha);
 	}
 }
@@ -1,21 +1,36 @@
 use clap::Parser;
 use data::{
-	channel::SongError, cli::Args, midi::MidiSong, song::Song, video::Encoding, window::Window,
+	channel::SongError,
+	cli::Args,
+	error::LoadError,
+	midi::MidiSong,
+	song::Song,
+	video::Encoding,
+	window::{FrameRate, Window},
 };
 use image::RgbImage;
 use indicatif::{ProgressBar, ProgressStyle};
 use lazy_static::lazy_static;
+use std::fmt;
 
 mod data;
 mod display;
 
 lazy_static! {
-	pub static ref WINDOW: Window = Window::load_from_args(&Args::parse());
+	pub static ref WINDOW: Window =
+		Window::load_from_args(&Args::parse()).unwrap_or_else(|err| exit_with_error(err));
 	pub static ref SCREEN_WIDTH: u32 = WINDOW.width;
 	pub static ref SCREEN_HEIGHT: u32 = WINDOW.height;
 	pub static ref SCREEN_SCALE: u32 = WINDOW.scale;
-	pub static ref SCREEN_FRAME_RATE: usize = WINDOW.frame_rate;
+	pub static ref SCREEN_FRAME_RATE: FrameRate = WINDOW.frame_rate;
 	pub static ref SCREEN_DURATION_SECS: f64 = WINDOW.duration_secs;
+	pub static ref SCREEN_SUPERSAMPLE: bool = WINDOW.supersample && WINDOW.scale > 1;
+}
+
+/// Print an error that stopped the song from loading or rendering, and exit
+fn exit_with_error(err: impl fmt::Display) -> ! {
+	eprintln!("\n{err}\n");
+	std::process::exit(1);
 }
 
 fn generate_progressbar(total: u64) -> ProgressBar {
@@ -30,86 +45,103 @@ fn generate_progressbar(total: u64) -> ProgressBar {
 	pb
 }
 
-fn encode_wavs(cmd: &Args) {
+fn encode_wavs(cmd: &Args) -> Result<(), LoadError> {
 	// Step 1: Set up project and encoder
-	let mut song = Song::load_from_file(&cmd.song);
-	let mut encoding = Encoding::new(&song.video_file_out);
+	let mut song = Song::load_from_file(&cmd.song)?;
+	let mut encoding = Encoding::new(&song.video_file_out, &song.master_audio, song.start_secs);
 	let mut frame = RgbImage::new(*SCREEN_WIDTH, *SCREEN_HEIGHT);
-	let pb = generate_progressbar(song.channels[0].play_time_samples_total);
+	let pb = generate_progressbar(((song.get_end_secs() - song.start_secs) * 1000.0) as u64);
 
 	// Step 2: Render waveforms
 	println!("\nStarting render");
 	loop {
 		let result = song.draw(&mut frame, &mut encoding);
 
-		pb.set_position(song.channels[0].play_time_samples);
+		pb.set_position(((song.playhead_secs - song.start_secs) * 1000.0) as u64);
 
 		// `err` can either be the end of the song, or a genuine fault.
 		// Either way, stop execution.
 		if result.is_err() {
 			// Step 3: Flush MP4 to file
-			encoding.flush();
+			let flushed = encoding.flush();
 			pb.finish();
 
-			match result.err().unwrap() {
-				SongError::End => println!("Finished rendering to {}", &song.video_file_out),
-				SongError::Error(err) => println!("{:?}", err),
+			if let SongError::Error(err) = result.err().unwrap() {
+				exit_with_error(format!("Rendering stopped early - Error: {err}"));
 			}
 
+			flushed.unwrap_or_else(|err| exit_with_error(err));
+			println!("Finished rendering to {}", &song.video_file_out);
+
 			break;
 		}
 	}
+
+	Ok(())
 }
 
-fn encode_midi(cmd: &Args) {
+fn encode_midi(cmd: &Args) -> Result<(), LoadError> {
 	// Step 1: Set up project and encoder
-	let mut midi = MidiSong::load_from_file(&cmd.midi);
-	let mut encoding = Encoding::new(&midi.config.video_file_out);
+	let mut midi = MidiSong::load_from_file(&cmd.midi)?;
+	let mut encoding = Encoding::new(
+		&midi.config.video_file_out,
+		&midi.config.master_audio,
+		midi.config.start_secs,
+	);
 	let mut frame = RgbImage::new(*SCREEN_WIDTH, *SCREEN_HEIGHT);
 
-	let pb = generate_progressbar(
-		((midi.get_song_duration() + (*SCREEN_DURATION_SECS / 2.0)) * 1000.0) as u64,
-	);
+	let pb = generate_progressbar(((midi.get_end_secs() - midi.config.start_secs) * 1000.0) as u64);
 
 	// Step 2: Render waveforms
 	println!("\nStarting render");
 	loop {
 		let result = midi.draw(&mut frame, &mut encoding);
 
-		pb.set_position(((midi.playhead_secs + (*SCREEN_DURATION_SECS / 2.0)) * 1000.0) as u64);
+		pb.set_position(
+			((midi.playhead_secs + (*SCREEN_DURATION_SECS / 2.0) - midi.config.start_secs) * 1000.0)
+				as u64,
+		);
 
 		// `err` can either be the end of the song, or a genuine fault.
 		// Either way, stop execution.
 		if result.is_err() {
 			// Step 3: Flush MP4 to file
-			encoding.flush();
+			let flushed = encoding.flush();
 			pb.finish();
 
-			match result.err().unwrap() {
-				SongError::End => println!("Finished rendering to {}", &midi.config.video_file_out),
-				SongError::Error(err) => println!("{:?}", err),
+			if let SongError::Error(err) = result.err().unwrap() {
+				exit_with_error(format!("Rendering stopped early - Error: {err}"));
 			}
 
+			flushed.unwrap_or_else(|err| exit_with_error(err));
+			println!("Finished rendering to {}", &midi.config.video_file_out);
+
 			break;
 		}
 	}
+
+	Ok(())
 }
 
 fn main() {
 	video_rs::init().expect("Could not initialise FFMPEG");
 
+	// Load the window config up front, so any errors are reported before the song loads
+	lazy_static::initialize(&WINDOW);
+
 	let mut cmd = Args::parse();
 
 	if cmd.midi.is_empty() && cmd.song.is_empty() {
 		cmd.song = String::from("./song.json");
-		return encode_wavs(&cmd);
 	}
 
-	if !cmd.song.is_empty() {
-		return encode_wavs(&cmd);
-	}
-
-	if !cmd.midi.is_empty() {
+	let result = if !cmd.song.is_empty() {
+		encode_wavs(&cmd)
+	} else {
 		encode_midi(&cmd)
+	};
+
+	if let Err(err) = result {
+		exit_with_error(err);
 	}
 }